@@ -9,6 +9,9 @@ pub fn instruction_derive(input: TokenStream) -> TokenStream {
 
 fn impl_instruction_macro(ast: &syn::DeriveInput) -> TokenStream {
     let mut content = String::new();
+    let mut all = String::new();
+    let mut names = String::new();
+    let mut from_names = String::new();
     if let syn::Data::Enum(e) = &ast.data {
         for v in &e.variants {
             let v = format!(
@@ -21,7 +24,37 @@ fn impl_instruction_macro(ast: &syn::DeriveInput) -> TokenStream {
             content.push_str(&v);
             content.push_str("\n");
         }
+        for v in &e.variants {
+            let name = v.ident.to_string().to_lowercase();
+            all.push_str(&format!("{}::{},", ast.ident, v.ident));
+            names.push_str(&format!("{}::{} => \"{}\",", ast.ident, v.ident, name));
+            from_names.push_str(&format!(
+                "\"{}\" => Some({}::{}),",
+                name, ast.ident, v.ident
+            ));
+        }
     };
 
+    content.push_str(&format!(
+        "impl {ty} {{
+            /// Every instruction, in declaration order.
+            pub const ALL: &'static [{ty}] = &[{all}];
+
+            /// Lowercase mnemonic used by the assembler.
+            pub fn name(&self) -> &'static str {{
+                match self {{ {names} }}
+            }}
+
+            /// Looks up an instruction by its lowercase mnemonic.
+            pub fn from_name(name: &str) -> Option<{ty}> {{
+                match name {{ {from_names} _ => None }}
+            }}
+        }}",
+        ty = ast.ident,
+        all = all,
+        names = names,
+        from_names = from_names
+    ));
+
     content.parse().unwrap()
 }
@@ -0,0 +1,883 @@
+//! Assembler for the textual `.svm` program format.
+//!
+//! Every non empty line holds one mnemonic, followed by a literal operand for
+//! the instructions that take one. Comments start with `;` or `#` and run to
+//! the end of the line.
+//!
+//! ```text
+//! ; c = a + b
+//! push 6
+//! push 4.5
+//! add
+//! store "c"
+//! push "done\n"
+//! write
+//! halt
+//! ```
+//!
+//! A line may start with one or more `name:` label definitions. `jmp`, `jif`
+//! and `call` accept a label name instead of a literal address:
+//!
+//! ```text
+//! loop:
+//!     push true
+//!     jif loop
+//! ```
+//!
+//! Literals are integers (`-3`), floats (`2.5`, `1e3`, `inf`, `NaN`), strings
+//! with the escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\"` and `\'`, chars (`'a'`),
+//! byte strings that also accept `\xNN` (`b"\x00\xff"`), booleans, `null`,
+//! arrays such as `[1, "two", [3.0]]` and maps such as `{"port": 80, 1: [2]}`
+//! whose keys are integers, strings, booleans, chars or bytes.
+//! The `.data` directive places a literal in the program without an
+//! instruction in front of it.
+
+use std::{collections::HashMap, fmt, iter::Peekable, str::Chars};
+
+use crate::{
+    builder::{LabelError, ProgramBuilder},
+    token::{
+        instruction::Instruction,
+        operand::{Operand, MAX_NESTING},
+        Token,
+    },
+};
+
+/// Assembles `src` into a program runnable by [`crate::Vm`].
+pub fn assemble(src: &str) -> Result<Vec<Token>, AsmError> {
+    assemble_at(src, 0)
+}
+
+/// Assembles `src` as code that starts at address `origin`, so labels resolve
+/// correctly when the result is appended to a program of that length.
+pub fn assemble_at(src: &str, origin: usize) -> Result<Vec<Token>, AsmError> {
+    Assembler::run(src, origin).map(|(program, _)| program)
+}
+
+/// Like [`assemble`] but also returns the address of every label, e.g. to set
+/// breakpoints by name.
+pub fn assemble_with_labels(src: &str) -> Result<(Vec<Token>, HashMap<String, usize>), AsmError> {
+    Assembler::run(src, 0)
+}
+
+struct Assembler {
+    builder: ProgramBuilder,
+    /// Position of the first reference to each label, to report undefined ones.
+    references: HashMap<String, (usize, usize)>,
+}
+
+impl Assembler {
+    fn run(src: &str, origin: usize) -> Result<(Vec<Token>, HashMap<String, usize>), AsmError> {
+        let mut asm = Assembler {
+            builder: ProgramBuilder::with_origin(origin),
+            references: HashMap::new(),
+        };
+        for (n, line) in src.lines().enumerate() {
+            asm.line(n + 1, line)?;
+        }
+        asm.finish()
+    }
+
+    fn line(&mut self, line: usize, src: &str) -> Result<(), AsmError> {
+        let mut lexer = Lexer::new(line, src);
+        let mut next = lexer.next_lexeme()?;
+        while let Some((column, Lexeme::Label(name))) = next {
+            if self.builder.has_label(&name) {
+                return Err(AsmError::new(
+                    line,
+                    column,
+                    AsmErrorKind::DuplicateLabel(name),
+                ));
+            }
+            self.builder.label(&name);
+            next = lexer.next_lexeme()?;
+        }
+        let (column, mnemonic) = match next {
+            Some((column, Lexeme::Word(w))) => (column, w),
+            Some((column, _)) => {
+                return Err(AsmError::new(line, column, AsmErrorKind::ExpectedMnemonic))
+            }
+            None => return Ok(()),
+        };
+        if mnemonic == ".data" {
+            match lexer.next_lexeme()? {
+                Some((_, Lexeme::Literal(v))) => self.builder.data(v),
+                _ => return Err(AsmError::new(line, column, AsmErrorKind::ExpectedLiteral)),
+            };
+            return match lexer.next_lexeme()? {
+                Some((column, _)) => Err(AsmError::new(line, column, AsmErrorKind::TrailingInput)),
+                None => Ok(()),
+            };
+        }
+        let instr = Instruction::from_name(&mnemonic.to_lowercase()).ok_or_else(|| {
+            AsmError::new(
+                line,
+                column,
+                AsmErrorKind::UnknownMnemonic(mnemonic.clone()),
+            )
+        })?;
+        self.builder.instr(instr.clone());
+
+        if instr.takes_operand() {
+            match lexer.next_lexeme()? {
+                Some((_, Lexeme::Literal(v))) => {
+                    self.builder.data(v);
+                }
+                Some((column, Lexeme::Word(w))) if instr.takes_address() => {
+                    self.references.entry(w.clone()).or_insert((line, column));
+                    self.builder.label_ref(&w);
+                }
+                Some((column, Lexeme::Word(w) | Lexeme::Label(w))) => {
+                    return Err(AsmError::new(line, column, AsmErrorKind::InvalidLiteral(w)))
+                }
+                None => {
+                    return Err(AsmError::new(
+                        line,
+                        lexer.column(),
+                        AsmErrorKind::MissingOperand(instr),
+                    ))
+                }
+            }
+        }
+        match lexer.next_lexeme()? {
+            Some((column, _)) => Err(AsmError::new(
+                line,
+                column,
+                AsmErrorKind::UnexpectedOperand(instr),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(self) -> Result<(Vec<Token>, HashMap<String, usize>), AsmError> {
+        let references = self.references;
+        let labels = self.builder.labels().clone();
+        let program = self.builder.build().map_err(|e| match e {
+            LabelError::Undefined(name) => {
+                let (line, column) = references[&name];
+                AsmError::new(line, column, AsmErrorKind::UndefinedLabel(name))
+            }
+            LabelError::Duplicate(_) => unreachable!("duplicates are rejected while assembling"),
+        })?;
+        Ok((program, labels))
+    }
+}
+
+/// An assembly error and the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrorKind {
+    /// The line starts with something other than a mnemonic.
+    ExpectedMnemonic,
+    UnknownMnemonic(String),
+    /// The instruction takes an operand but none was given.
+    MissingOperand(Instruction),
+    /// An operand was given to an instruction that takes none, or a second
+    /// operand was given.
+    UnexpectedOperand(Instruction),
+    InvalidLiteral(String),
+    /// `.data` must be followed by exactly one literal.
+    ExpectedLiteral,
+    TrailingInput,
+    UnterminatedString,
+    /// A character literal must hold exactly one character.
+    InvalidChar,
+    /// An array literal is missing its closing `]`.
+    UnterminatedArray,
+    /// A map literal is missing its closing `}`.
+    UnterminatedMap,
+    /// Map keys must be integers, strings, booleans, chars or bytes.
+    UnhashableKey,
+    /// Arrays and maps are nested deeper than [`MAX_NESTING`].
+    TooDeep,
+    InvalidEscape(char),
+    UnexpectedCharacter(char),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+}
+
+impl AsmError {
+    fn new(line: usize, column: usize, kind: AsmErrorKind) -> Self {
+        Self { line, column, kind }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            AsmErrorKind::ExpectedMnemonic => write!(f, "expected a mnemonic"),
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic `{}`", m),
+            AsmErrorKind::MissingOperand(i) => write!(f, "`{}` expects an operand", i.name()),
+            AsmErrorKind::UnexpectedOperand(i) => {
+                write!(f, "unexpected operand after `{}`", i.name())
+            }
+            AsmErrorKind::InvalidLiteral(l) => write!(f, "invalid literal `{}`", l),
+            AsmErrorKind::ExpectedLiteral => write!(f, "expected a literal"),
+            AsmErrorKind::TrailingInput => write!(f, "unexpected input at end of line"),
+            AsmErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            AsmErrorKind::InvalidChar => write!(f, "invalid character literal"),
+            AsmErrorKind::UnterminatedArray => write!(f, "unterminated array"),
+            AsmErrorKind::UnterminatedMap => write!(f, "unterminated map"),
+            AsmErrorKind::UnhashableKey => {
+                write!(
+                    f,
+                    "map keys must be integers, strings, booleans, chars or bytes"
+                )
+            }
+            AsmErrorKind::TooDeep => write!(f, "literal nested too deeply"),
+            AsmErrorKind::InvalidEscape(c) => write!(f, "invalid escape `\\{}`", c),
+            AsmErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label `{}`", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "duplicate label `{}`", l),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug, PartialEq)]
+enum Lexeme {
+    Word(String),
+    /// A word directly followed by `:`.
+    Label(String),
+    Literal(Operand),
+}
+
+struct Lexer<'a> {
+    line: usize,
+    column: usize,
+    chars: Peekable<Chars<'a>>,
+    /// Arrays and maps being read.
+    depth: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(line: usize, src: &'a str) -> Self {
+        Self {
+            line,
+            column: 1,
+            chars: src.chars().peekable(),
+            depth: 0,
+        }
+    }
+
+    /// Column of the next character to be read.
+    fn column(&self) -> usize {
+        self.column
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.column += 1;
+        Some(c)
+    }
+
+    fn error(&self, column: usize, kind: AsmErrorKind) -> AsmError {
+        AsmError::new(self.line, column, kind)
+    }
+
+    fn next_lexeme(&mut self) -> Result<Option<(usize, Lexeme)>, AsmError> {
+        self.skip_whitespace();
+        let column = self.column;
+        let c = match self.chars.peek() {
+            None | Some(';') | Some('#') => return Ok(None),
+            Some(c) => *c,
+        };
+        let lexeme = if c == '"' {
+            self.bump();
+            Lexeme::Literal(Operand::Str(self.string(column)?))
+        } else if c == '\'' {
+            self.bump();
+            Lexeme::Literal(Operand::Char(self.char(column)?))
+        } else if c == 'b' && self.chars.clone().nth(1) == Some('"') {
+            self.bump();
+            self.bump();
+            Lexeme::Literal(Operand::Bytes(self.bytes(column)?))
+        } else if c == '[' {
+            self.bump();
+            Lexeme::Literal(self.nested(column, Self::array)?)
+        } else if c == '{' {
+            self.bump();
+            Lexeme::Literal(self.nested(column, Self::map)?)
+        } else if c == '.' && self.chars.clone().nth(1).is_some_and(char::is_alphabetic) {
+            self.bump();
+            Lexeme::Word(format!(".{}", self.word()))
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            Lexeme::Literal(self.number(column)?)
+        } else if c.is_alphabetic() || c == '_' {
+            let word = self.word();
+            if self.chars.peek() == Some(&':') {
+                self.bump();
+                return Ok(Some((column, Lexeme::Label(word))));
+            }
+            match word.as_str() {
+                "true" => Lexeme::Literal(Operand::Bool(true)),
+                "false" => Lexeme::Literal(Operand::Bool(false)),
+                "null" => Lexeme::Literal(Operand::Null),
+                "inf" => Lexeme::Literal(Operand::Float(f64::INFINITY)),
+                "NaN" => Lexeme::Literal(Operand::Float(f64::NAN)),
+                _ => Lexeme::Word(word),
+            }
+        } else {
+            return Err(self.error(column, AsmErrorKind::UnexpectedCharacter(c)));
+        };
+        Ok(Some((column, lexeme)))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    /// Reads an array or map literal with `read`, failing if it is nested
+    /// deeper than [`MAX_NESTING`].
+    fn nested(
+        &mut self,
+        column: usize,
+        read: fn(&mut Self, usize) -> Result<Operand, AsmError>,
+    ) -> Result<Operand, AsmError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error(column, AsmErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let v = read(self, column);
+        self.depth -= 1;
+        v
+    }
+
+    /// Reads the elements of an array literal, the `[` is already consumed.
+    fn array(&mut self, column: usize) -> Result<Operand, AsmError> {
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.bump();
+            return Ok(Operand::Array(elements));
+        }
+        loop {
+            elements.push(self.element(column, ']')?);
+            if self.separator(column, ']')? {
+                return Ok(Operand::Array(elements));
+            }
+        }
+    }
+
+    /// Reads the entries of a map literal, the `{` is already consumed.
+    fn map(&mut self, column: usize) -> Result<Operand, AsmError> {
+        let mut map = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.bump();
+            return Ok(Operand::Map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key_column = self.column;
+            let key = self.element(column, '}')?;
+            if !key.is_hashable() {
+                return Err(self.error(key_column, AsmErrorKind::UnhashableKey));
+            }
+            self.skip_whitespace();
+            let next = self.column;
+            match self.bump() {
+                Some(':') => {}
+                Some(c) => return Err(self.error(next, AsmErrorKind::UnexpectedCharacter(c))),
+                None => return Err(self.unterminated(column, '}')),
+            }
+            map.insert(key, self.element(column, '}')?);
+            if self.separator(column, '}')? {
+                return Ok(Operand::Map(map));
+            }
+        }
+    }
+
+    /// Reads the `,` or `close` after an element, returning whether it was
+    /// `close`.
+    fn separator(&mut self, column: usize, close: char) -> Result<bool, AsmError> {
+        self.skip_whitespace();
+        let next = self.column;
+        match self.bump() {
+            Some(',') => Ok(false),
+            Some(c) if c == close => Ok(true),
+            Some(c) => Err(self.error(next, AsmErrorKind::UnexpectedCharacter(c))),
+            None => Err(self.unterminated(column, close)),
+        }
+    }
+
+    fn unterminated(&self, column: usize, close: char) -> AsmError {
+        let kind = if close == ']' {
+            AsmErrorKind::UnterminatedArray
+        } else {
+            AsmErrorKind::UnterminatedMap
+        };
+        self.error(column, kind)
+    }
+
+    /// Reads one literal inside an array or map that starts at `column` and
+    /// ends with `close`.
+    fn element(&mut self, column: usize, close: char) -> Result<Operand, AsmError> {
+        self.skip_whitespace();
+        let start = self.column;
+        match self.chars.peek().copied() {
+            None => Err(self.unterminated(column, close)),
+            Some('"') => {
+                self.bump();
+                self.string(start).map(Operand::Str)
+            }
+            Some('\'') => {
+                self.bump();
+                self.char(start).map(Operand::Char)
+            }
+            Some('b') if self.chars.clone().nth(1) == Some('"') => {
+                self.bump();
+                self.bump();
+                self.bytes(start).map(Operand::Bytes)
+            }
+            Some('[') => {
+                self.bump();
+                self.nested(start, Self::array)
+            }
+            Some('{') => {
+                self.bump();
+                self.nested(start, Self::map)
+            }
+            Some(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => self.number(start),
+            Some(c) if c.is_alphabetic() => match self.word().as_str() {
+                "true" => Ok(Operand::Bool(true)),
+                "false" => Ok(Operand::Bool(false)),
+                "null" => Ok(Operand::Null),
+                "inf" => Ok(Operand::Float(f64::INFINITY)),
+                "NaN" => Ok(Operand::Float(f64::NAN)),
+                word => Err(self.error(start, AsmErrorKind::InvalidLiteral(word.to_owned()))),
+            },
+            Some(c) => Err(self.error(start, AsmErrorKind::UnexpectedCharacter(c))),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    fn number(&mut self, column: usize) -> Result<Operand, AsmError> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            let sign = (c == '-' || c == '+') && (text.is_empty() || text.ends_with(['e', 'E']));
+            if !(c.is_alphanumeric() || c == '.' || sign) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        if let Ok(v) = text.parse::<i64>() {
+            return Ok(Operand::Int(v));
+        }
+        match text.parse::<f64>() {
+            Ok(v) => Ok(Operand::Float(v)),
+            Err(_) => Err(self.error(column, AsmErrorKind::InvalidLiteral(text))),
+        }
+    }
+
+    /// Reads a string literal, the opening quote is already consumed.
+    fn string(&mut self, column: usize) -> Result<String, AsmError> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error(column, AsmErrorKind::UnterminatedString)),
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.escape(column)?),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Reads a byte string literal, the opening `b"` is already consumed.
+    /// Besides the string escapes it accepts `\xNN` for arbitrary bytes.
+    fn bytes(&mut self, column: usize) -> Result<Vec<u8>, AsmError> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error(column, AsmErrorKind::UnterminatedString)),
+                Some('"') => return Ok(bytes),
+                Some('\\') if self.chars.peek() == Some(&'x') => {
+                    let escape_column = self.column - 1;
+                    self.bump();
+                    let digits: String = (0..2).filter_map(|_| self.bump()).collect();
+                    let byte = u8::from_str_radix(&digits, 16)
+                        .ok()
+                        .filter(|_| digits.len() == 2)
+                        .ok_or_else(|| {
+                            self.error(escape_column, AsmErrorKind::InvalidEscape('x'))
+                        })?;
+                    bytes.push(byte);
+                }
+                Some(c) => {
+                    let c = if c == '\\' { self.escape(column)? } else { c };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+            }
+        }
+    }
+
+    /// Reads a character literal, the opening `'` is already consumed.
+    fn char(&mut self, column: usize) -> Result<char, AsmError> {
+        let c = match self.bump() {
+            Some('\\') => self.escape(column)?,
+            Some('\'') | None => return Err(self.error(column, AsmErrorKind::InvalidChar)),
+            Some(c) => c,
+        };
+        match self.bump() {
+            Some('\'') => Ok(c),
+            _ => Err(self.error(column, AsmErrorKind::InvalidChar)),
+        }
+    }
+
+    /// Reads the character after a `\` in a literal starting at `column`.
+    fn escape(&mut self, column: usize) -> Result<char, AsmError> {
+        let escape_column = self.column - 1;
+        match self.bump() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('\'') => Ok('\''),
+            Some(c) => Err(self.error(escape_column, AsmErrorKind::InvalidEscape(c))),
+            None => Err(self.error(column, AsmErrorKind::UnterminatedString)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, assemble_with_labels, AsmError, AsmErrorKind};
+    use crate::{
+        tbool, tfloat, tint,
+        token::{
+            instruction::{Instruction, *},
+            operand::{Operand, MAX_NESTING},
+            Token,
+        },
+        tstr, Vm,
+    };
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble(
+            "; add two numbers
+            push 10
+            PUSH 12   # mnemonics are case insensitive
+            add
+
+            halt",
+        )
+        .unwrap();
+        assert_eq!(program, vec![PUSH, tint!(10), PUSH, tint!(12), ADD, HALT]);
+    }
+
+    #[test]
+    fn test_literals() {
+        let program = assemble(
+            r#"push -3
+            push 2.5
+            push 1e3
+            push "a \"b\"\n"
+            push true
+            push false
+            push null
+            push [1, [], ["x", -2.5]]
+            push {"a": {}, 2: [true], false: null}
+            push ['a', '\'', b"\x00\"é"]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![
+                PUSH,
+                tint!(-3),
+                PUSH,
+                tfloat!(2.5),
+                PUSH,
+                tfloat!(1000.0),
+                PUSH,
+                tstr!(String::from("a \"b\"\n")),
+                PUSH,
+                tbool!(true),
+                PUSH,
+                tbool!(false),
+                PUSH,
+                Token::Data(Operand::Null),
+                PUSH,
+                Token::Data(Operand::Array(vec![
+                    Operand::Int(1),
+                    Operand::Array(vec![]),
+                    Operand::Array(vec![Operand::Str(String::from("x")), Operand::Float(-2.5)]),
+                ])),
+                PUSH,
+                Token::Data(Operand::Map(
+                    [
+                        (
+                            Operand::Str(String::from("a")),
+                            Operand::Map(Default::default())
+                        ),
+                        (Operand::Int(2), Operand::Array(vec![Operand::Bool(true)])),
+                        (Operand::Bool(false), Operand::Null),
+                    ]
+                    .into_iter()
+                    .collect()
+                )),
+                PUSH,
+                Token::Data(Operand::Array(vec![
+                    Operand::Char('a'),
+                    Operand::Char('\''),
+                    Operand::Bytes(vec![0, b'"', 0xc3, 0xa9]),
+                ])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_assembled() {
+        let program = assemble(
+            r#"push 6
+            store "a"
+            load "a"
+            push 4
+            mul
+            halt"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Int(24)));
+    }
+
+    #[test]
+    fn test_labels() {
+        let program = assemble(
+            r#"    push 6
+                push 4
+                call max
+                halt
+            max: store "b"
+                store "a"
+                load "a"
+                load "b"
+                isge
+                jif a_is_max
+                load "b"
+                ret
+            a_is_max:
+                load "a"
+                ret"#,
+        )
+        .unwrap();
+        assert_eq!(program[4..6], [CALL, tint!(7)]);
+        assert_eq!(program[16..18], [JIF, tint!(21)]);
+
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Int(6)));
+    }
+
+    #[test]
+    fn test_assemble_with_labels() {
+        let (program, labels) = assemble_with_labels("start: push 1\nend: halt").unwrap();
+        assert_eq!(program, vec![PUSH, tint!(1), HALT]);
+        assert_eq!(labels["start"], 0);
+        assert_eq!(labels["end"], 2);
+    }
+
+    #[test]
+    fn test_label_errors() {
+        assert_eq!(
+            assemble("jmp end\nhalt\n  jif end"),
+            Err(AsmError {
+                line: 1,
+                column: 5,
+                kind: AsmErrorKind::UndefinedLabel(String::from("end"))
+            })
+        );
+        assert_eq!(
+            assemble("a: halt\n a: halt"),
+            Err(AsmError {
+                line: 2,
+                column: 2,
+                kind: AsmErrorKind::DuplicateLabel(String::from("a"))
+            })
+        );
+        assert_eq!(
+            assemble("load a"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::InvalidLiteral(String::from("a"))
+            })
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble("push 1\n  frob"),
+            Err(AsmError {
+                line: 2,
+                column: 3,
+                kind: AsmErrorKind::UnknownMnemonic(String::from("frob"))
+            })
+        );
+        assert_eq!(
+            assemble("push"),
+            Err(AsmError {
+                line: 1,
+                column: 5,
+                kind: AsmErrorKind::MissingOperand(Instruction::Push)
+            })
+        );
+        assert_eq!(
+            assemble("add 1"),
+            Err(AsmError {
+                line: 1,
+                column: 5,
+                kind: AsmErrorKind::UnexpectedOperand(Instruction::Add)
+            })
+        );
+        assert_eq!(
+            assemble("push 1 2"),
+            Err(AsmError {
+                line: 1,
+                column: 8,
+                kind: AsmErrorKind::UnexpectedOperand(Instruction::Push)
+            })
+        );
+        assert_eq!(
+            assemble("push \"abc"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::UnterminatedString
+            })
+        );
+        assert_eq!(
+            assemble("push \"\\q\""),
+            Err(AsmError {
+                line: 1,
+                column: 7,
+                kind: AsmErrorKind::InvalidEscape('q')
+            })
+        );
+        assert_eq!(
+            assemble("push 1.2.3"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::InvalidLiteral(String::from("1.2.3"))
+            })
+        );
+        assert_eq!(
+            assemble("push [1, 2"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::UnterminatedArray
+            })
+        );
+        let deep = |n| format!("push {}{}", "[".repeat(n), "]".repeat(n));
+        assert!(assemble(&deep(MAX_NESTING)).is_ok());
+        assert_eq!(
+            assemble(&deep(200_000)),
+            Err(AsmError {
+                line: 1,
+                column: 6 + MAX_NESTING,
+                kind: AsmErrorKind::TooDeep
+            })
+        );
+        assert_eq!(
+            assemble("push [1 2]"),
+            Err(AsmError {
+                line: 1,
+                column: 9,
+                kind: AsmErrorKind::UnexpectedCharacter('2')
+            })
+        );
+        assert_eq!(
+            assemble("push {1: 2, [3]: 4}"),
+            Err(AsmError {
+                line: 1,
+                column: 13,
+                kind: AsmErrorKind::UnhashableKey
+            })
+        );
+        assert_eq!(
+            assemble("push {1 2}"),
+            Err(AsmError {
+                line: 1,
+                column: 9,
+                kind: AsmErrorKind::UnexpectedCharacter('2')
+            })
+        );
+        assert_eq!(
+            assemble("push [{1: 2"),
+            Err(AsmError {
+                line: 1,
+                column: 7,
+                kind: AsmErrorKind::UnterminatedMap
+            })
+        );
+        assert_eq!(
+            assemble("push 'ab'"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::InvalidChar
+            })
+        );
+        assert_eq!(
+            assemble("push ''"),
+            Err(AsmError {
+                line: 1,
+                column: 6,
+                kind: AsmErrorKind::InvalidChar
+            })
+        );
+        assert_eq!(
+            assemble("push b\"\\x4\""),
+            Err(AsmError {
+                line: 1,
+                column: 8,
+                kind: AsmErrorKind::InvalidEscape('x')
+            })
+        );
+        assert_eq!(
+            assemble("push [x]"),
+            Err(AsmError {
+                line: 1,
+                column: 7,
+                kind: AsmErrorKind::InvalidLiteral(String::from("x"))
+            })
+        );
+        assert_eq!(
+            assemble("10"),
+            Err(AsmError {
+                line: 1,
+                column: 1,
+                kind: AsmErrorKind::ExpectedMnemonic
+            })
+        );
+    }
+}
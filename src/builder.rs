@@ -0,0 +1,216 @@
+use std::{collections::HashMap, fmt};
+
+use crate::token::{instruction::Instruction, operand::Operand, Token};
+
+/// Builds a program in Rust code, resolving symbolic labels to absolute
+/// addresses once the whole program is known.
+///
+/// ```
+/// use svm::builder::ProgramBuilder;
+/// use svm::token::{instruction::Instruction, operand::Operand};
+///
+/// let mut b = ProgramBuilder::new();
+/// b.push(Operand::Int(3))
+///     .jump_to(Instruction::Call, "double")
+///     .instr(Instruction::Halt)
+///     .label("double")
+///     .push(Operand::Int(2))
+///     .instr(Instruction::Mul)
+///     .instr(Instruction::Ret);
+/// let program = b.build().unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct ProgramBuilder {
+    /// Address of the first token, for code appended to an existing program.
+    origin: usize,
+    tokens: Vec<Token>,
+    labels: HashMap<String, usize>,
+    /// Data tokens waiting for the address of a label.
+    references: Vec<(usize, String)>,
+    duplicate: Option<String>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A builder for code that will be placed at `origin`, e.g. appended to a
+    /// program that is already `origin` tokens long.
+    pub fn with_origin(origin: usize) -> Self {
+        Self {
+            origin,
+            ..Self::default()
+        }
+    }
+
+    /// Address the next token will be placed at.
+    pub fn address(&self) -> usize {
+        self.origin + self.tokens.len()
+    }
+
+    /// Every label defined so far with its address.
+    pub fn labels(&self) -> &HashMap<String, usize> {
+        &self.labels
+    }
+
+    /// Whether `name` was already defined.
+    pub fn has_label(&self, name: &str) -> bool {
+        self.labels.contains_key(name)
+    }
+
+    pub fn instr(&mut self, i: Instruction) -> &mut Self {
+        self.tokens.push(Token::Instruction(i));
+        self
+    }
+
+    pub fn data(&mut self, v: Operand) -> &mut Self {
+        self.tokens.push(Token::Data(v));
+        self
+    }
+
+    /// Emits an instruction followed by its operand.
+    pub fn instr_with(&mut self, i: Instruction, v: Operand) -> &mut Self {
+        self.instr(i).data(v)
+    }
+
+    pub fn push(&mut self, v: Operand) -> &mut Self {
+        self.instr_with(Instruction::Push, v)
+    }
+
+    /// Defines `name` as the address of the next token.
+    pub fn label(&mut self, name: &str) -> &mut Self {
+        let address = self.address();
+        if self.labels.insert(name.to_owned(), address).is_some() && self.duplicate.is_none() {
+            self.duplicate = Some(name.to_owned());
+        }
+        self
+    }
+
+    /// Emits `i` (usually `Jmp`, `Jif` or `Call`) with the address of `label`
+    /// as its operand. The label may be defined later.
+    pub fn jump_to(&mut self, i: Instruction, label: &str) -> &mut Self {
+        self.instr(i);
+        self.label_ref(label)
+    }
+
+    /// Emits a data token holding the address of `label`.
+    pub fn label_ref(&mut self, label: &str) -> &mut Self {
+        self.references.push((self.address(), label.to_owned()));
+        self.data(Operand::Null)
+    }
+
+    /// Resolves every label reference and returns the program.
+    pub fn build(mut self) -> Result<Vec<Token>, LabelError> {
+        if let Some(name) = self.duplicate {
+            return Err(LabelError::Duplicate(name));
+        }
+        for (at, name) in &self.references {
+            let address = self
+                .labels
+                .get(name)
+                .ok_or_else(|| LabelError::Undefined(name.clone()))?;
+            self.tokens[*at - self.origin] = Token::Data(Operand::Int(*address as i64));
+        }
+        Ok(self.tokens)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelError {
+    Undefined(String),
+    Duplicate(String),
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelError::Undefined(name) => write!(f, "undefined label `{}`", name),
+            LabelError::Duplicate(name) => write!(f, "duplicate label `{}`", name),
+        }
+    }
+}
+
+impl std::error::Error for LabelError {}
+
+#[cfg(test)]
+mod test {
+    use super::{LabelError, ProgramBuilder};
+    use crate::{
+        tint,
+        token::{
+            instruction::{Instruction, *},
+            operand::Operand,
+        },
+        tstr, Vm,
+    };
+
+    #[test]
+    fn test_max_ab() {
+        let a = Operand::Str(String::from("a"));
+        let b = Operand::Str(String::from("b"));
+        let mut builder = ProgramBuilder::new();
+        builder
+            .push(Operand::Int(6))
+            .push(Operand::Int(4))
+            .jump_to(Instruction::Call, "max")
+            .instr(Instruction::Halt)
+            .label("max")
+            .instr_with(Instruction::Store, b.clone())
+            .instr_with(Instruction::Store, a.clone())
+            .instr_with(Instruction::Load, a.clone())
+            .instr_with(Instruction::Load, b.clone())
+            .instr(Instruction::Isge)
+            .jump_to(Instruction::Jif, "a")
+            .instr_with(Instruction::Load, b)
+            .instr(Instruction::Ret)
+            .label("a")
+            .instr_with(Instruction::Load, a)
+            .instr(Instruction::Ret);
+        let program = builder.build().unwrap();
+        assert_eq!(program[4..6], [CALL, tint!(7)]);
+        assert_eq!(program[16..18], [JIF, tint!(21)]);
+        assert_eq!(program[21..23], [LOAD, tstr!(String::from("a"))]);
+
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Int(6)));
+    }
+
+    #[test]
+    fn test_backward_reference() {
+        let mut builder = ProgramBuilder::new();
+        builder
+            .label("start")
+            .instr(Instruction::Halt)
+            .jump_to(Instruction::Jmp, "start");
+        assert_eq!(builder.build(), Ok(vec![HALT, JMP, tint!(0)]));
+    }
+
+    #[test]
+    fn test_origin() {
+        let mut builder = ProgramBuilder::with_origin(10);
+        builder
+            .instr(Instruction::Halt)
+            .label("end")
+            .jump_to(Instruction::Jmp, "end");
+        assert_eq!(builder.build(), Ok(vec![HALT, JMP, tint!(11)]));
+    }
+
+    #[test]
+    fn test_label_errors() {
+        let mut builder = ProgramBuilder::new();
+        builder.jump_to(Instruction::Jmp, "nowhere");
+        assert_eq!(
+            builder.build(),
+            Err(LabelError::Undefined(String::from("nowhere")))
+        );
+
+        let mut builder = ProgramBuilder::new();
+        builder.label("a").instr(Instruction::Halt).label("a");
+        assert_eq!(
+            builder.build(),
+            Err(LabelError::Duplicate(String::from("a")))
+        );
+    }
+}
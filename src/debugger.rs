@@ -0,0 +1,356 @@
+//! Step debugger with breakpoints on top of [`Vm`].
+//!
+//! The [`Debugger`] can be driven from code or interactively through
+//! [`Debugger::run`], which reads the commands listed in [`HELP`].
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    disasm,
+    token::{instruction::Instruction, operand::Operand, Token},
+    Vm, VmError,
+};
+
+pub const HELP: &str = "break <addr|label>   set a breakpoint (b)
+delete <addr|label>  clear a breakpoint (d)
+step                 execute one instruction, entering calls (s)
+next                 execute one instruction, stepping over calls (n)
+finish               run until the current function returns (f)
+continue             run until a breakpoint or halt (c)
+stack                show the operand stack, top first
+frames               show the call frames, innermost first (bt)
+vars                 show the variables of the current frame
+where                show the next instruction (w)
+help                 show this help
+quit                 leave the debugger (q)";
+
+/// Why execution stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// The requested step completed.
+    Stepped,
+    /// The instruction at this address has a breakpoint and is next to run.
+    Breakpoint(usize),
+    Halted,
+}
+
+pub struct Debugger {
+    vm: Vm,
+    breakpoints: BTreeSet<usize>,
+    labels: HashMap<String, usize>,
+}
+
+impl Debugger {
+    pub fn new(vm: Vm) -> Self {
+        Self {
+            vm,
+            breakpoints: BTreeSet::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Makes `labels` usable wherever an address is expected.
+    pub fn with_labels(mut self, labels: HashMap<String, usize>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    /// Resolves a label name or a numeric address.
+    pub fn resolve(&self, location: &str) -> Option<usize> {
+        self.labels
+            .get(location)
+            .copied()
+            .or_else(|| location.parse().ok())
+    }
+
+    pub fn set_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn clear_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Executes one instruction, entering calls.
+    pub fn step_into(&mut self) -> Result<Stop, VmError> {
+        if self.vm.is_halted() {
+            return Ok(Stop::Halted);
+        }
+        self.vm.step()?;
+        Ok(if self.vm.is_halted() {
+            Stop::Halted
+        } else {
+            Stop::Stepped
+        })
+    }
+
+    /// Executes one instruction, running a `Call` until it returns.
+    pub fn step_over(&mut self) -> Result<Stop, VmError> {
+        let is_call = matches!(
+            self.vm.program().get(self.vm.ip()),
+            Some(Token::Instruction(Instruction::Call))
+        );
+        if !is_call {
+            return self.step_into();
+        }
+        let depth = self.depth();
+        self.run_until(|vm| vm.frames().count() <= depth)
+    }
+
+    /// Runs until the current function returns to its caller. In the root
+    /// frame this is the same as [`Debugger::resume`].
+    pub fn step_out(&mut self) -> Result<Stop, VmError> {
+        let depth = self.depth();
+        self.run_until(|vm| vm.frames().count() < depth)
+    }
+
+    /// Runs until a breakpoint or `Halt`.
+    pub fn resume(&mut self) -> Result<Stop, VmError> {
+        self.run_until(|_| false)
+    }
+
+    fn depth(&self) -> usize {
+        self.vm.frames().count()
+    }
+
+    /// Steps at least once, then until `done` holds, a breakpoint is reached
+    /// or the vm halts.
+    fn run_until(&mut self, done: impl Fn(&Vm) -> bool) -> Result<Stop, VmError> {
+        loop {
+            if let Stop::Halted = self.step_into()? {
+                return Ok(Stop::Halted);
+            }
+            if done(&self.vm) {
+                return Ok(Stop::Stepped);
+            }
+            if self.breakpoints.contains(&self.vm.ip()) {
+                return Ok(Stop::Breakpoint(self.vm.ip()));
+            }
+        }
+    }
+
+    /// The next instruction, e.g. `0007: storelocal 0 ; b` with the name of
+    /// the local it accesses.
+    pub fn location(&self) -> String {
+        let ip = self.vm.ip();
+        let Some(text) = disasm::disassemble_at(self.vm.program(), ip) else {
+            return format!("{:04}: <end of program>", ip);
+        };
+        let names = (self.vm.local_names()).names(self.vm.current_frame().function());
+        let name = match self.vm.program().get(ip..ip + 2) {
+            Some(
+                [Token::Instruction(Instruction::Loadlocal | Instruction::Storelocal), Token::Data(Operand::Int(slot))],
+            ) => names.get(*slot as usize).filter(|name| !name.is_empty()),
+            _ => None,
+        };
+        match name {
+            Some(name) => format!("{:04}: {} ; {}", ip, text, name),
+            None => format!("{:04}: {}", ip, text),
+        }
+    }
+
+    pub fn stack(&self) -> String {
+        let mut out = String::new();
+        for v in self.vm.stack() {
+            let _ = writeln!(out, "  {:#}", v);
+        }
+        out
+    }
+
+    pub fn frames(&self) -> String {
+        let mut out = String::new();
+        let depth = self.depth();
+        for (n, frame) in self.vm.frames().enumerate() {
+            if n + 1 == depth {
+                let _ = writeln!(out, "  #{} <root>", n);
+            } else {
+                let _ = writeln!(out, "  #{} returns to {:04}", n, frame.return_address());
+            }
+        }
+        out
+    }
+
+    pub fn vars(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.vm.variables() {
+            let _ = writeln!(out, "  {} = {:#}", name, value);
+        }
+        out
+    }
+
+    /// Reads debugger commands from `input` until it ends or `quit`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.location())?;
+        write!(output, "(svm) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let (command, arg) = (words.next().unwrap_or(""), words.next());
+            if matches!(command, "quit" | "q") {
+                break;
+            }
+            write!(output, "{}", self.command(command, arg))?;
+            write!(output, "(svm) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, command: &str, arg: Option<&str>) -> String {
+        let stop = match command {
+            "" => return String::new(),
+            "break" | "b" | "delete" | "d" => {
+                let Some(address) = arg.and_then(|arg| self.resolve(arg)) else {
+                    return String::from("expected an address or a known label\n");
+                };
+                return if matches!(command, "break" | "b") {
+                    self.set_breakpoint(address);
+                    format!("breakpoint at {:04}\n", address)
+                } else if self.clear_breakpoint(address) {
+                    format!("deleted breakpoint at {:04}\n", address)
+                } else {
+                    format!("no breakpoint at {:04}\n", address)
+                };
+            }
+            "step" | "s" => self.step_into(),
+            "next" | "n" => self.step_over(),
+            "finish" | "f" => self.step_out(),
+            "continue" | "c" => self.resume(),
+            "stack" => return self.stack(),
+            "frames" | "bt" => return self.frames(),
+            "vars" => return self.vars(),
+            "where" | "w" => return format!("{}\n", self.location()),
+            "help" => return format!("{}\n", HELP),
+            _ => return format!("unknown command `{}`, try help\n", command),
+        };
+        match stop {
+            Ok(Stop::Halted) => String::from("halted\n"),
+            Ok(Stop::Breakpoint(_)) => format!("breakpoint, {}\n", self.location()),
+            Ok(Stop::Stepped) => format!("{}\n", self.location()),
+            Err(e) => format!("error: {}\n", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{Debugger, Stop};
+    use crate::{asm, Vm};
+
+    const MAX: &str = r#"
+            push 6
+            push 4
+            call max
+            halt
+        max:
+            store "b"
+            store "a"
+            load "a"
+            load "b"
+            isge
+            jif a
+            load "b"
+            ret
+        a:  load "a"
+            ret"#;
+
+    fn debugger() -> Debugger {
+        let (program, labels) = asm::assemble_with_labels(MAX).unwrap();
+        Debugger::new(Vm::new(program).unwrap()).with_labels(labels)
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger();
+        let max = dbg.resolve("max").unwrap();
+        assert_eq!(max, 7);
+        dbg.set_breakpoint(max);
+        dbg.set_breakpoint(dbg.resolve("a").unwrap());
+
+        assert_eq!(dbg.resume(), Ok(Stop::Breakpoint(7)));
+        assert_eq!(dbg.vm().frames().count(), 2);
+        assert_eq!(dbg.resume(), Ok(Stop::Breakpoint(21)));
+        assert_eq!(dbg.vm().variable("a"), 6);
+        assert!(dbg.clear_breakpoint(21));
+        assert!(!dbg.clear_breakpoint(21));
+        assert_eq!(dbg.resume(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut dbg = debugger();
+        assert_eq!(dbg.step_into(), Ok(Stop::Stepped));
+        assert_eq!(dbg.step_over(), Ok(Stop::Stepped));
+        // Stepping over the call lands right after it with the result pushed.
+        assert_eq!(dbg.step_over(), Ok(Stop::Stepped));
+        assert_eq!(dbg.vm().ip(), 6);
+        assert_eq!(dbg.vm().stack().len(), 1);
+
+        let mut dbg = debugger();
+        dbg.step_into().unwrap();
+        dbg.step_into().unwrap();
+        dbg.step_into().unwrap();
+        assert_eq!(dbg.vm().ip(), 7);
+        assert_eq!(dbg.step_out(), Ok(Stop::Stepped));
+        assert_eq!(dbg.vm().ip(), 6);
+        assert_eq!(dbg.vm().frames().count(), 1);
+
+        // A breakpoint inside the callee interrupts stepping over the call.
+        let mut dbg = debugger();
+        dbg.set_breakpoint(9);
+        dbg.step_into().unwrap();
+        dbg.step_into().unwrap();
+        assert_eq!(dbg.step_over(), Ok(Stop::Breakpoint(9)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut dbg = Debugger::new(Vm::new(asm::assemble("pop").unwrap()).unwrap());
+        assert!(dbg.step_into().is_err());
+        let mut dbg = Debugger::new(Vm::new(asm::assemble("halt").unwrap()).unwrap());
+        assert_eq!(dbg.resume(), Ok(Stop::Halted));
+        assert_eq!(dbg.step_into(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn test_interactive() {
+        let mut dbg = debugger();
+        let mut output = Vec::new();
+        dbg.run(
+            Cursor::new("b max\nc\nbt\ns\nvars\nstack\nd max\nc\nq\n"),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"0000: push 6
+(svm) breakpoint at 0007
+(svm) breakpoint, 0007: storelocal 0 ; b
+(svm)   #0 returns to 0006
+  #1 <root>
+(svm) 0009: storelocal 1 ; a
+(svm)   a = null
+  b = 4
+(svm)   6
+(svm) deleted breakpoint at 0007
+(svm) halted
+(svm) "#
+        );
+    }
+}
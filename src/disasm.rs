@@ -0,0 +1,269 @@
+//! Renders a program back into the `.svm` assembly format.
+//!
+//! Every instruction is printed on its own line together with its operand and
+//! its address as a trailing comment. Targets of `jmp`, `jif` and `call` get a
+//! synthesized `L<address>` label, so the listing can be edited and fed back to
+//! [`crate::asm::assemble`].
+
+use std::collections::BTreeSet;
+
+use crate::token::{operand::Operand, Token};
+
+/// Disassembles `program` into a listing that assembles back to the same tokens.
+///
+/// Data tokens that are not the operand of an instruction are emitted with the
+/// `.data` directive. An instruction missing its trailing operand is printed
+/// on its own and will not assemble again.
+pub fn disassemble(program: &[Token]) -> String {
+    let lines = group(program);
+    let starts: BTreeSet<usize> = lines.iter().map(|(address, _, _)| *address).collect();
+    let targets: BTreeSet<usize> = lines
+        .iter()
+        .filter_map(|(_, token, operand)| match (token, operand) {
+            (Token::Instruction(i), Some(Operand::Int(target))) if i.takes_address() => {
+                usize::try_from(*target).ok()
+            }
+            _ => None,
+        })
+        .filter(|target| {
+            starts.contains(target) && matches!(program[*target], Token::Instruction(_))
+        })
+        .collect();
+
+    let mut out = String::new();
+    for (address, token, operand) in lines {
+        if targets.contains(&address) {
+            out.push_str(&format!("{}:\n", label(address)));
+        }
+        let text = match (token, operand) {
+            (Token::Instruction(i), Some(Operand::Int(target)))
+                if i.takes_address()
+                    && usize::try_from(*target).is_ok_and(|t| targets.contains(&t)) =>
+            {
+                format!("{} {}", i, label(*target as usize))
+            }
+            (Token::Instruction(i), Some(v)) => format!("{} {}", i, literal(v)),
+            (Token::Instruction(i), None) => i.to_string(),
+            (Token::Data(v), _) => format!(".data {}", literal(v)),
+        };
+        out.push_str(&format!("    {:<24} ; {:04}\n", text, address));
+    }
+    out
+}
+
+/// Renders the instruction at `address` with its operand, or `None` if the
+/// address is past the end of the program.
+pub fn disassemble_at(program: &[Token], address: usize) -> Option<String> {
+    let text = match (program.get(address)?, program.get(address + 1)) {
+        (Token::Instruction(i), Some(Token::Data(v))) if i.takes_operand() => {
+            format!("{} {}", i, literal(v))
+        }
+        (Token::Instruction(i), _) => i.to_string(),
+        (Token::Data(v), _) => format!(".data {}", literal(v)),
+    };
+    Some(text)
+}
+
+/// Splits the program into lines of (address, token, operand).
+fn group(program: &[Token]) -> Vec<(usize, &Token, Option<&Operand>)> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let token = &program[address];
+        let operand = match (token, program.get(address + 1)) {
+            (Token::Instruction(i), Some(Token::Data(v))) if i.takes_operand() => Some(v),
+            _ => None,
+        };
+        lines.push((address, token, operand));
+        address += if operand.is_some() { 2 } else { 1 };
+    }
+    lines
+}
+
+fn label(address: usize) -> String {
+    format!("L{}", address)
+}
+
+/// Formats `v` the way the assembler reads it back.
+fn literal(v: &Operand) -> String {
+    match v {
+        Operand::Str(s) => {
+            let mut out = String::from('"');
+            for c in s.chars() {
+                escape(c, &mut out);
+            }
+            out.push('"');
+            out
+        }
+        Operand::Char('\'') => String::from("'\\''"),
+        Operand::Char(c) => {
+            let mut out = String::from('\'');
+            escape(*c, &mut out);
+            out.push('\'');
+            out
+        }
+        Operand::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(literal).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Operand::Map(map) => {
+            let entries: Vec<String> = Operand::sorted_entries(map)
+                .into_iter()
+                .map(|(k, v)| format!("{}: {}", literal(k), literal(v)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        // Their display form is valid assembly.
+        Operand::Null
+        | Operand::Int(_)
+        | Operand::Float(_)
+        | Operand::Bool(_)
+        | Operand::Bytes(_) => v.to_string(),
+    }
+}
+
+fn escape(c: char, out: &mut String) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\t' => out.push_str("\\t"),
+        '\r' => out.push_str("\\r"),
+        '\0' => out.push_str("\\0"),
+        '\\' => out.push_str("\\\\"),
+        '"' => out.push_str("\\\""),
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disassemble, disassemble_at};
+    use crate::{
+        asm::assemble,
+        tbool, tfloat, tint,
+        token::{instruction::*, operand::Operand, Token},
+        tstr,
+    };
+
+    #[test]
+    fn test_listing() {
+        let program = vec![
+            PUSH,
+            tint!(3),
+            CALL,
+            tint!(5),
+            HALT,
+            PUSH,
+            tint!(2),
+            MUL,
+            RET,
+        ];
+        assert_eq!(
+            disassemble(&program),
+            "    push 3                   ; 0000
+    call L5                  ; 0002
+    halt                     ; 0004
+L5:
+    push 2                   ; 0005
+    mul                      ; 0007
+    ret                      ; 0008
+"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_at() {
+        let program = vec![PUSH, tint!(3), HALT];
+        assert_eq!(disassemble_at(&program, 0), Some(String::from("push 3")));
+        assert_eq!(disassemble_at(&program, 1), Some(String::from(".data 3")));
+        assert_eq!(disassemble_at(&program, 2), Some(String::from("halt")));
+        assert_eq!(disassemble_at(&program, 3), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let a = String::from("a");
+        let b = String::from("b");
+        let programs = vec![
+            vec![
+                PUSH,
+                tint!(6),
+                PUSH,
+                tint!(4),
+                CALL,
+                tint!(7),
+                HALT,
+                STORE,
+                tstr!(b.clone()),
+                STORE,
+                tstr!(a.clone()),
+                LOAD,
+                tstr!(a.clone()),
+                LOAD,
+                tstr!(b.clone()),
+                ISGE,
+                JIF,
+                tint!(21),
+                LOAD,
+                tstr!(b),
+                RET,
+                LOAD,
+                tstr!(a),
+                RET,
+            ],
+            vec![
+                PUSH,
+                tfloat!(1.0),
+                PUSH,
+                tfloat!(-2.5e-8),
+                PUSH,
+                tstr!(String::from("tab\t\"quoted\"\\\n")),
+                PUSH,
+                tbool!(false),
+                PUSH,
+                Token::Data(Operand::Null),
+                PUSH,
+                Token::Data(Operand::Array(vec![
+                    Operand::Int(1),
+                    Operand::Array(vec![]),
+                    Operand::Str(String::from("]")),
+                ])),
+                PUSH,
+                Token::Data(Operand::Array(vec![
+                    Operand::Char('x'),
+                    Operand::Char('\''),
+                    Operand::Char('\n'),
+                    Operand::Char('"'),
+                    Operand::Bytes(vec![0, b'a', b'"', b'\\', 0xff]),
+                    Operand::Bytes(vec![]),
+                ])),
+                PUSH,
+                Token::Data(Operand::Map(
+                    [
+                        (Operand::Str(String::from("a")), Operand::Array(vec![])),
+                        (Operand::Int(-1), Operand::Map(Default::default())),
+                        (Operand::Bool(true), Operand::Null),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )),
+                WRITE,
+                HALT,
+            ],
+            // Jump targets that are not instructions stay numeric and stray
+            // data is kept with `.data`.
+            vec![
+                JMP,
+                tint!(1),
+                JIF,
+                tint!(99),
+                tint!(7),
+                CALL,
+                tstr!(String::from("x")),
+            ],
+        ];
+        for program in programs {
+            let listing = disassemble(&program);
+            assert_eq!(assemble(&listing), Ok(program), "{}", listing);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use crate::token::{instruction::Instruction, operand::Operand};
+
+/// Errors raised while executing a program. Every variant carries the `ip`
+/// of the instruction that faulted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// The instruction needed more values than the stack holds.
+    StackUnderflow { ip: usize, instr: Instruction },
+    /// A binary instruction was applied to operands it does not support.
+    TypeMismatch {
+        ip: usize,
+        instr: Instruction,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// A unary instruction or an inline operand had the wrong type.
+    InvalidOperand {
+        ip: usize,
+        instr: Instruction,
+        operand: Operand,
+    },
+    /// The instruction expects a data token after it but there is none.
+    MissingOperand { ip: usize, instr: Instruction },
+    /// `Jmp`, `Jif` or `Call` pointed outside of the program.
+    InvalidJumpTarget { ip: usize, target: Operand },
+    /// Integer division by zero.
+    DivisionByZero { ip: usize },
+    /// An integer result did not fit in 64 bits.
+    IntegerOverflow { ip: usize, instr: Instruction },
+    /// `Ret` was executed with no call frame to return from.
+    ReturnWithoutCall { ip: usize },
+    /// The instruction has no [`crate::token::op::Op`] to run it as.
+    UnsupportedInstruction { ip: usize, instr: Instruction },
+    /// The instruction pointer landed on a data token.
+    DataExecuted { ip: usize },
+    /// The instruction pointer ran past the end of the program.
+    IpOutOfBounds { ip: usize },
+    /// Pushing would exceed `VmConfig::max_stack_size`.
+    StackOverflow { ip: usize, limit: usize },
+    /// Calling would exceed `VmConfig::max_call_depth`.
+    CallDepthExceeded { ip: usize, limit: usize },
+    /// The frames would hold more local slots than `VmConfig::max_locals`.
+    LocalsExceeded { ip: usize, limit: usize },
+    /// An array was indexed outside of `0..len`. Popping an empty array
+    /// reports index `-1`.
+    IndexOutOfBounds { ip: usize, index: i64, len: usize },
+    /// `Loadlocal` or `Storelocal` used a slot past the size of the frame.
+    SlotOutOfBounds { ip: usize, slot: usize, size: usize },
+    /// Bytes converted to a string were not valid UTF-8.
+    InvalidUtf8 { ip: usize },
+    /// `Syscall` was given an id that is not in the syscall table.
+    UnknownSyscall { ip: usize, id: i64 },
+    /// Reading or writing on behalf of the program failed.
+    Io { ip: usize, message: String },
+    /// `Callnative` named a function that is not registered.
+    UnknownNative { ip: usize, native: Operand },
+    /// A native function failed, see [`VmError::native`].
+    Native {
+        ip: usize,
+        name: String,
+        message: String,
+    },
+    /// The fuel budget ran out before the instruction at `ip` was executed.
+    /// Unlike the other errors this leaves the vm untouched, so execution can
+    /// continue after adding more fuel.
+    OutOfFuel { ip: usize },
+}
+
+impl VmError {
+    /// The error for a native function to return. The vm fills in the
+    /// address of the call and the name of the function.
+    pub fn native(message: impl Into<String>) -> VmError {
+        VmError::Native {
+            ip: 0,
+            name: String::new(),
+            message: message.into(),
+        }
+    }
+
+    /// Address of the instruction that faulted.
+    pub fn ip(&self) -> usize {
+        match self {
+            VmError::StackUnderflow { ip, .. }
+            | VmError::TypeMismatch { ip, .. }
+            | VmError::InvalidOperand { ip, .. }
+            | VmError::MissingOperand { ip, .. }
+            | VmError::InvalidJumpTarget { ip, .. }
+            | VmError::DivisionByZero { ip }
+            | VmError::IntegerOverflow { ip, .. }
+            | VmError::ReturnWithoutCall { ip }
+            | VmError::UnsupportedInstruction { ip, .. }
+            | VmError::DataExecuted { ip }
+            | VmError::IpOutOfBounds { ip }
+            | VmError::StackOverflow { ip, .. }
+            | VmError::CallDepthExceeded { ip, .. }
+            | VmError::LocalsExceeded { ip, .. }
+            | VmError::IndexOutOfBounds { ip, .. }
+            | VmError::SlotOutOfBounds { ip, .. }
+            | VmError::InvalidUtf8 { ip }
+            | VmError::UnknownSyscall { ip, .. }
+            | VmError::Io { ip, .. }
+            | VmError::UnknownNative { ip, .. }
+            | VmError::Native { ip, .. }
+            | VmError::OutOfFuel { ip } => *ip,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::StackUnderflow { ip, instr } => {
+                write!(f, "stack underflow at {}: {} needs more values", ip, instr)
+            }
+            VmError::TypeMismatch {
+                ip,
+                instr,
+                lhs,
+                rhs,
+            } => write!(
+                f,
+                "type mismatch at {}: cannot apply {} to {:#} and {:#}",
+                ip, instr, lhs, rhs
+            ),
+            VmError::InvalidOperand { ip, instr, operand } => {
+                write!(f, "invalid operand at {}: {} got {:#}", ip, instr, operand)
+            }
+            VmError::MissingOperand { ip, instr } => {
+                write!(f, "missing operand at {}: {} expects data", ip, instr)
+            }
+            VmError::InvalidJumpTarget { ip, target } => {
+                write!(f, "invalid jump target at {}: {:#}", ip, target)
+            }
+            VmError::DivisionByZero { ip } => write!(f, "division by zero at {}", ip),
+            VmError::IntegerOverflow { ip, instr } => {
+                write!(
+                    f,
+                    "integer overflow at {}: {} does not fit in 64 bits",
+                    ip, instr
+                )
+            }
+            VmError::ReturnWithoutCall { ip } => {
+                write!(f, "return without call at {}", ip)
+            }
+            VmError::UnsupportedInstruction { ip, instr } => {
+                write!(f, "unsupported instruction at {}: {}", ip, instr)
+            }
+            VmError::DataExecuted { ip } => write!(f, "cannot execute data at {}", ip),
+            VmError::IpOutOfBounds { ip } => write!(f, "ip out of bounds at {}", ip),
+            VmError::StackOverflow { ip, limit } => {
+                write!(f, "stack overflow at {}: limit is {} values", ip, limit)
+            }
+            VmError::CallDepthExceeded { ip, limit } => {
+                write!(f, "call depth exceeded at {}: limit is {} calls", ip, limit)
+            }
+            VmError::LocalsExceeded { ip, limit } => {
+                write!(f, "too many locals at {}: limit is {} slots", ip, limit)
+            }
+            VmError::IndexOutOfBounds { ip, index, len } => write!(
+                f,
+                "index out of bounds at {}: index {} but the length is {}",
+                ip, index, len
+            ),
+            VmError::SlotOutOfBounds { ip, slot, size } => write!(
+                f,
+                "slot out of bounds at {}: slot {} but the frame has {}",
+                ip, slot, size
+            ),
+            VmError::InvalidUtf8 { ip } => write!(f, "invalid UTF-8 at {}", ip),
+            VmError::UnknownSyscall { ip, id } => write!(f, "unknown syscall {} at {}", id, ip),
+            VmError::Io { ip, message } => write!(f, "i/o error at {}: {}", ip, message),
+            VmError::UnknownNative { ip, native } => {
+                write!(f, "unknown native function {:#} at {}", native, ip)
+            }
+            VmError::Native { ip, name, message } => {
+                write!(f, "native function {} failed at {}: {}", name, ip, message)
+            }
+            VmError::OutOfFuel { ip } => write!(f, "out of fuel at {}", ip),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
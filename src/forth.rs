@@ -0,0 +1,750 @@
+//! A Forth dialect compiled to svm programs.
+//!
+//! ```text
+//! : square ( n -- n*n ) dup * ;
+//! : countdown ( n -- ) begin dup . 1 - dup 0= until drop ;
+//! 3 square .          \ prints "9 "
+//! 3 countdown         \ prints "3 2 1 "
+//! ```
+//!
+//! Words are separated by whitespace and case insensitive. Numbers push
+//! themselves, `s" text"` pushes a string and `." text"` prints one.
+//! Comments are `( .. )` and `\` to the end of the line.
+//!
+//! ```text
+//! + - * / mod              arithmetic, / truncates integers
+//! = <> < > <= >= 0=        comparisons, they push booleans
+//! true false and or invert on booleans
+//! dup drop swap over rot   stack shuffling
+//! . emit cr                print a value and a space, a char, a newline
+//! : name .. ;              define a word, compiled as a function
+//! recurse                  call the word being defined
+//! if .. else .. then       `if` pops a boolean
+//! begin .. until           loop until the popped boolean is true
+//! begin .. while .. repeat loop while the popped boolean is true
+//! limit start do .. loop   count from start up to limit, `i` and `j`
+//!                          push the index of the inner and outer loop
+//! variable name            `name @` fetches and `v name !` stores
+//! ```
+//!
+//! Definitions can appear between top level code, words must be defined
+//! before they are used and a later definition replaces an earlier one.
+//!
+//! Each call runs in its own vm frame, so variables are local: a variable
+//! declared at the top level can only be used at the top level, one
+//! declared inside a definition only inside it and a fresh copy exists for
+//! every call. Variables and the hidden ones used by the words above are
+//! compiled to numbered slots, [`compile_with_locals`] also returns their
+//! names for [`crate::Vm::with_local_names`].
+
+use std::{collections::HashMap, fmt, iter::Peekable, str::Chars};
+
+use crate::{
+    builder::ProgramBuilder,
+    frame::LocalNames,
+    token::{instruction::Instruction, operand::Operand, Token},
+};
+
+/// Compiles Forth source into a program runnable by [`crate::Vm`].
+pub fn compile(src: &str) -> Result<Vec<Token>, ForthError> {
+    compile_with_locals(src).map(|(program, _)| program)
+}
+
+/// Like [`compile`], also returning the names of the variable slots.
+pub fn compile_with_locals(src: &str) -> Result<(Vec<Token>, LocalNames), ForthError> {
+    let mut compiler = Compiler {
+        words: Scanner {
+            chars: src.chars().peekable(),
+            line: 1,
+            column: 1,
+        },
+        builder: ProgramBuilder::new(),
+        dictionary: HashMap::new(),
+        definition: None,
+        control: Vec::new(),
+        labels: 0,
+        at: (1, 1),
+    };
+    while let Some((line, column, word)) = compiler.words.next_word()? {
+        compiler.at = (line, column);
+        compiler
+            .word(&word)
+            .map_err(|kind| ForthError { line, column, kind })?;
+    }
+    let mut program = compiler.finish()?;
+    let mut locals = LocalNames::new();
+    locals.resolve(&mut program);
+    Ok((program, locals))
+}
+
+/// A compile error and the 1-based line and column of the word it was found
+/// at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForthError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ForthErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForthErrorKind {
+    UndefinedWord(String),
+    /// A word like `then` without its opening `if`, or `;` outside of a
+    /// definition.
+    UnexpectedWord(String),
+    /// The input ended inside the construct opened by this word, e.g. `:`
+    /// or `(`.
+    Unterminated(String),
+    /// `:` and `variable` must be followed by a name.
+    MissingName(String),
+    /// `:` inside a definition.
+    NestedDefinition,
+    /// A variable not followed by `@` or `!`.
+    InvalidVariableUse(String),
+}
+
+impl fmt::Display for ForthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ForthErrorKind::UndefinedWord(w) => write!(f, "undefined word `{}`", w),
+            ForthErrorKind::UnexpectedWord(w) => write!(f, "unexpected `{}`", w),
+            ForthErrorKind::Unterminated(w) => write!(f, "unterminated `{}`", w),
+            ForthErrorKind::MissingName(w) => write!(f, "`{}` expects a name", w),
+            ForthErrorKind::NestedDefinition => write!(f, "definitions cannot be nested"),
+            ForthErrorKind::InvalidVariableUse(v) => {
+                write!(f, "variable `{}` must be followed by `@` or `!`", v)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ForthError {}
+
+/// Splits the source into words, reading the text of strings and comments
+/// on request.
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Scanner<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// The next word with its line and column.
+    fn next_word(&mut self) -> Result<Option<(usize, usize, String)>, ForthError> {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+        let (line, column) = (self.line, self.column);
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek().copied() {
+            if c.is_whitespace() {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        if word.is_empty() {
+            return Ok(None);
+        }
+        let word = word.to_lowercase();
+        // Skip comments here so the compiler never sees them.
+        match word.as_str() {
+            "\\" => {
+                while self.chars.peek().is_some_and(|&c| c != '\n') {
+                    self.bump();
+                }
+                self.next_word()
+            }
+            "(" => {
+                self.until(')')
+                    .ok_or_else(|| ForthError::unterminated(line, column, &word))?;
+                self.next_word()
+            }
+            _ => Ok(Some((line, column, word))),
+        }
+    }
+
+    /// Text up to `end`, skipping the space after the opening word. `None`
+    /// if the input ends first.
+    fn until(&mut self, end: char) -> Option<String> {
+        if self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+        let mut text = String::new();
+        loop {
+            match self.bump()? {
+                c if c == end => return Some(text),
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+impl ForthError {
+    fn unterminated(line: usize, column: usize, word: &str) -> Self {
+        Self {
+            line,
+            column,
+            kind: ForthErrorKind::Unterminated(word.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    /// A defined word and the label of its code.
+    Word(String),
+    Variable,
+}
+
+/// An open control structure.
+enum Control {
+    If { otherwise: String, end: String },
+    Else { end: String },
+    Begin { start: String },
+    While { start: String, end: String },
+    Do { start: String },
+}
+
+/// The definition being compiled.
+struct Definition {
+    name: String,
+    label: String,
+    line: usize,
+    column: usize,
+    /// Label to jump to after the definition, it is compiled inline.
+    skip: String,
+    /// Entries declared inside the definition, visible only there.
+    locals: HashMap<String, Entry>,
+}
+
+struct Compiler<'a> {
+    words: Scanner<'a>,
+    builder: ProgramBuilder,
+    dictionary: HashMap<String, Entry>,
+    definition: Option<Definition>,
+    control: Vec<Control>,
+    labels: usize,
+    /// Line and column of the word being compiled.
+    at: (usize, usize),
+}
+
+impl Compiler<'_> {
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn emit(&mut self, instrs: &[Instruction]) {
+        for i in instrs {
+            self.builder.instr(i.clone());
+        }
+    }
+
+    /// Pops the values on top of the stack into hidden variables `t1`, `t2`,
+    /// .. with `t1` the deepest. Their names contain a space so they cannot
+    /// clash with Forth variables.
+    fn spill(&mut self, n: usize) {
+        for k in (1..=n).rev() {
+            self.builder
+                .instr_with(Instruction::Store, Operand::Str(format!(" t{}", k)));
+        }
+    }
+
+    /// Pushes the hidden variables in `order`.
+    fn reload(&mut self, order: &[usize]) {
+        for k in order {
+            self.builder
+                .instr_with(Instruction::Load, Operand::Str(format!(" t{}", k)));
+        }
+    }
+
+    /// Hidden variables holding the index and limit of the loop `depth`
+    /// levels out from the innermost one.
+    fn loop_variables(&self, depth: usize) -> Option<(Operand, Operand)> {
+        let level = self
+            .control
+            .iter()
+            .filter(|c| matches!(c, Control::Do { .. }))
+            .count()
+            .checked_sub(depth + 1)?;
+        Some((
+            Operand::Str(format!(" i{}", level)),
+            Operand::Str(format!(" limit{}", level)),
+        ))
+    }
+
+    fn lookup(&self, word: &str) -> Option<Entry> {
+        self.definition
+            .as_ref()
+            .and_then(|d| d.locals.get(word))
+            .or_else(|| match self.definition {
+                // Top level variables belong to the root frame.
+                Some(_) => self
+                    .dictionary
+                    .get(word)
+                    .filter(|e| matches!(e, Entry::Word(_))),
+                None => self.dictionary.get(word),
+            })
+            .cloned()
+    }
+
+    fn declare(&mut self, name: String, entry: Entry) {
+        match (&mut self.definition, &entry) {
+            (Some(d), Entry::Variable) => d.locals.insert(name, entry),
+            _ => self.dictionary.insert(name, entry),
+        };
+    }
+
+    fn name(&mut self, word: &str) -> Result<String, ForthErrorKind> {
+        self.words
+            .next_word()
+            .ok()
+            .flatten()
+            .map(|(_, _, name)| name)
+            .ok_or_else(|| ForthErrorKind::MissingName(word.to_owned()))
+    }
+
+    fn string(&mut self, word: &str) -> Result<String, ForthErrorKind> {
+        self.words
+            .until('"')
+            .ok_or_else(|| ForthErrorKind::Unterminated(word.to_owned()))
+    }
+
+    fn unexpected(word: &str) -> ForthErrorKind {
+        ForthErrorKind::UnexpectedWord(word.to_owned())
+    }
+
+    fn word(&mut self, word: &str) -> Result<(), ForthErrorKind> {
+        use Instruction::*;
+
+        if let Some(entry) = self.lookup(word) {
+            match entry {
+                Entry::Word(label) => {
+                    self.builder.jump_to(Call, &label);
+                }
+                Entry::Variable => {
+                    let name = Operand::Str(word.to_owned());
+                    match self.words.next_word().ok().flatten() {
+                        Some((_, _, w)) if w == "@" => self.builder.instr_with(Load, name),
+                        Some((_, _, w)) if w == "!" => self.builder.instr_with(Store, name),
+                        _ => return Err(ForthErrorKind::InvalidVariableUse(word.to_owned())),
+                    };
+                }
+            }
+            return Ok(());
+        }
+
+        match word {
+            "+" => self.emit(&[Add]),
+            "-" => self.emit(&[Sub]),
+            "*" => self.emit(&[Mul]),
+            "/" => self.emit(&[Div]),
+            "mod" => {
+                // a - a / b * b
+                self.spill(2);
+                self.reload(&[1, 1, 2]);
+                self.emit(&[Div]);
+                self.reload(&[2]);
+                self.emit(&[Mul, Sub]);
+            }
+            "=" => self.emit(&[Iseq]),
+            "<>" => self.emit(&[Iseq, Not]),
+            ">" => self.emit(&[Isgt]),
+            ">=" => self.emit(&[Isge]),
+            "<" | "<=" => {
+                self.spill(2);
+                self.reload(&[2, 1]);
+                self.emit(&[if word == "<" { Isgt } else { Isge }]);
+            }
+            "0=" => {
+                self.builder.push(Operand::Int(0)).instr(Iseq);
+            }
+            "true" | "false" => {
+                self.builder.push(Operand::Bool(word == "true"));
+            }
+            "and" => self.emit(&[And]),
+            "or" => self.emit(&[Or]),
+            "invert" => self.emit(&[Not]),
+            "dup" => self.emit(&[Dup]),
+            "drop" => self.emit(&[Pop]),
+            "swap" => {
+                self.spill(2);
+                self.reload(&[2, 1]);
+            }
+            "over" => {
+                self.spill(2);
+                self.reload(&[1, 2, 1]);
+            }
+            "rot" => {
+                self.spill(3);
+                self.reload(&[2, 3, 1]);
+            }
+            "." => {
+                self.emit(&[Write, Pop]);
+                self.builder.push(Operand::Char(' '));
+                self.emit(&[Write, Pop]);
+            }
+            "emit" => self.emit(&[Tochar, Write, Pop]),
+            "cr" => {
+                self.builder.push(Operand::Char('\n'));
+                self.emit(&[Write, Pop]);
+            }
+            ".\"" => {
+                let text = self.string(word)?;
+                self.builder.push(Operand::Str(text));
+                self.emit(&[Write, Pop]);
+            }
+            "s\"" => {
+                let text = self.string(word)?;
+                self.builder.push(Operand::Str(text));
+            }
+            "variable" => {
+                let name = self.name(word)?;
+                self.declare(name, Entry::Variable);
+            }
+            ":" => {
+                if self.definition.is_some() {
+                    return Err(ForthErrorKind::NestedDefinition);
+                }
+                // Code outside the definition must not close its structures.
+                if !self.control.is_empty() {
+                    return Err(Self::unexpected(word));
+                }
+                let (line, column) = self.at;
+                let name = self.name(word)?;
+                let (label, skip) = (self.label(), self.label());
+                self.builder.jump_to(Jmp, &skip).label(&label);
+                self.definition = Some(Definition {
+                    name,
+                    label,
+                    line,
+                    column,
+                    skip,
+                    locals: HashMap::new(),
+                });
+            }
+            ";" => {
+                if !self.control.is_empty() {
+                    return Err(Self::unexpected(word));
+                }
+                let d = self
+                    .definition
+                    .take()
+                    .ok_or_else(|| Self::unexpected(word))?;
+                self.emit(&[Ret]);
+                self.builder.label(&d.skip);
+                // Visible only from here on, except through `recurse`.
+                self.dictionary.insert(d.name, Entry::Word(d.label));
+            }
+            "recurse" => {
+                let label = match &self.definition {
+                    Some(d) => d.label.clone(),
+                    None => return Err(Self::unexpected(word)),
+                };
+                self.builder.jump_to(Call, &label);
+            }
+            "if" => {
+                let (otherwise, end) = (self.label(), self.label());
+                self.builder.instr(Not).jump_to(Jif, &otherwise);
+                self.control.push(Control::If { otherwise, end });
+            }
+            "else" => match self.control.pop() {
+                Some(Control::If { otherwise, end }) => {
+                    self.builder.jump_to(Jmp, &end).label(&otherwise);
+                    self.control.push(Control::Else { end });
+                }
+                _ => return Err(Self::unexpected(word)),
+            },
+            "then" => match self.control.pop() {
+                Some(Control::If { otherwise, end }) => {
+                    self.builder.label(&otherwise).label(&end);
+                }
+                Some(Control::Else { end }) => {
+                    self.builder.label(&end);
+                }
+                _ => return Err(Self::unexpected(word)),
+            },
+            "begin" => {
+                let start = self.label();
+                self.builder.label(&start);
+                self.control.push(Control::Begin { start });
+            }
+            "until" => match self.control.pop() {
+                Some(Control::Begin { start }) => {
+                    self.builder.instr(Not).jump_to(Jif, &start);
+                }
+                _ => return Err(Self::unexpected(word)),
+            },
+            "while" => match self.control.pop() {
+                Some(Control::Begin { start }) => {
+                    let end = self.label();
+                    self.builder.instr(Not).jump_to(Jif, &end);
+                    self.control.push(Control::While { start, end });
+                }
+                _ => return Err(Self::unexpected(word)),
+            },
+            "repeat" => match self.control.pop() {
+                Some(Control::While { start, end }) => {
+                    self.builder.jump_to(Jmp, &start).label(&end);
+                }
+                _ => return Err(Self::unexpected(word)),
+            },
+            "do" => {
+                let start = self.label();
+                self.control.push(Control::Do {
+                    start: start.clone(),
+                });
+                let (index, limit) = self.loop_variables(0).expect("a loop was just opened");
+                self.builder
+                    .instr_with(Store, index)
+                    .instr_with(Store, limit)
+                    .label(&start);
+            }
+            "loop" => {
+                let start = match self.control.last() {
+                    Some(Control::Do { start }) => start.clone(),
+                    _ => return Err(Self::unexpected(word)),
+                };
+                let (index, limit) = self.loop_variables(0).expect("inside a loop");
+                self.control.pop();
+                // Runs the body again while index + 1 < limit.
+                self.builder
+                    .instr_with(Load, index.clone())
+                    .push(Operand::Int(1))
+                    .instr(Add)
+                    .instr_with(Store, index.clone())
+                    .instr_with(Load, limit)
+                    .instr_with(Load, index)
+                    .instr(Isgt)
+                    .jump_to(Jif, &start);
+            }
+            "i" | "j" => {
+                let depth = usize::from(word == "j");
+                let (index, _) = self
+                    .loop_variables(depth)
+                    .ok_or_else(|| Self::unexpected(word))?;
+                self.builder.instr_with(Load, index);
+            }
+            _ => {
+                let literal = word
+                    .parse()
+                    .map(Operand::Int)
+                    .or_else(|_| word.parse().map(Operand::Float))
+                    .map_err(|_| ForthErrorKind::UndefinedWord(word.to_owned()))?;
+                self.builder.push(literal);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<Token>, ForthError> {
+        if let Some(d) = &self.definition {
+            return Err(ForthError::unterminated(d.line, d.column, ":"));
+        }
+        if let Some(open) = self.control.last() {
+            let word = match open {
+                Control::If { .. } | Control::Else { .. } => "if",
+                Control::Begin { .. } | Control::While { .. } => "begin",
+                Control::Do { .. } => "do",
+            };
+            return Err(ForthError::unterminated(
+                self.words.line,
+                self.words.column,
+                word,
+            ));
+        }
+        self.builder.instr(Instruction::Halt);
+        Ok(self.builder.build().expect("generated labels are defined"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compile, compile_with_locals, ForthError, ForthErrorKind};
+    use crate::{
+        output::Capture,
+        token::{
+            instruction::{LOAD, STORE},
+            operand::Operand,
+        },
+        verify, Vm,
+    };
+
+    /// Runs `src` and returns what it printed and the stack from the bottom.
+    fn run(src: &str) -> (String, Vec<Operand>) {
+        let program = compile(src).unwrap();
+        assert_eq!(verify(&program), Ok(()));
+        let capture = Capture::new();
+        let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+        vm.run().unwrap();
+        let mut stack: Vec<_> = std::iter::from_fn(|| vm.pop()).collect();
+        stack.reverse();
+        (capture.contents(), stack)
+    }
+
+    fn output(src: &str) -> String {
+        run(src).0
+    }
+
+    #[test]
+    fn test_arithmetic_and_stack() {
+        assert_eq!(output("2 3 + 4 * ."), "20 ");
+        assert_eq!(output("7 2 / . 7 2 mod . -7 2 mod ."), "3 1 -1 ");
+        assert_eq!(
+            output("1 2 swap . . 1 2 over . . . 1 2 3 rot . . ."),
+            "1 2 1 2 1 1 3 2 "
+        );
+        assert_eq!(
+            output("1 2 < . 2 2 <= . 1 2 > . 3 0= . 1 2 <> ."),
+            "true true false false true "
+        );
+        assert_eq!(
+            run("1 2 drop dup 2.5 s\" hi\"").1,
+            [
+                Operand::Int(1),
+                Operand::Int(1),
+                Operand::Float(2.5),
+                Operand::Str(String::from("hi"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_words() {
+        let src = r#"
+            : square ( n -- n*n ) dup * ;
+            : cube dup square * ;
+            3 cube .
+            \ Redefining only affects later uses.
+            : square drop 0 ;
+            2 cube . 2 square .
+        "#;
+        assert_eq!(output(src), "27 8 0 ");
+    }
+
+    #[test]
+    fn test_factorial() {
+        let src = "
+            : fact ( n -- n! ) dup 1 > if dup 1 - recurse * then ;
+            10 fact .
+        ";
+        assert_eq!(output(src), "3628800 ");
+    }
+
+    #[test]
+    fn test_fibonacci() {
+        let src = "
+            : fib ( n -- ) 0 1 rot 0 do over . swap over + loop drop drop ;
+            10 fib
+        ";
+        assert_eq!(output(src), "0 1 1 2 3 5 8 13 21 34 ");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        let src = r#"
+            : fizz? 3 mod 0= dup if ." Fizz" then ;
+            : buzz? 5 mod 0= dup if ." Buzz" then ;
+            : fizzbuzz ( n -- )
+                dup fizz? over buzz? or if drop else . then ;
+            : run 16 1 do i fizzbuzz ."  " loop ;
+            run
+        "#;
+        assert_eq!(
+            output(src),
+            "1  2  Fizz 4  Buzz Fizz 7  8  Fizz Buzz 11  Fizz 13  14  FizzBuzz "
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eq!(
+            output(": countdown begin dup . 1 - dup 0= until drop ; 3 countdown"),
+            "3 2 1 "
+        );
+        assert_eq!(
+            output("0 begin dup 3 < while dup . 1 + repeat drop"),
+            "0 1 2 "
+        );
+        assert_eq!(output("3 1 do 3 1 do i j * . loop loop cr"), "1 2 2 4 \n");
+        assert_eq!(output("65 emit 66 emit cr .\" done\""), "AB\ndone");
+    }
+
+    #[test]
+    fn test_variables() {
+        let src = "
+            variable total
+            0 total !
+            5 0 do i total @ + total ! loop
+            total @ .
+            : twice variable x x ! x @ x @ + ;
+            21 twice .
+        ";
+        assert_eq!(output(src), "10 42 ");
+
+        let (program, locals) = compile_with_locals(src).unwrap();
+        assert!(!program.contains(&LOAD) && !program.contains(&STORE));
+        assert_eq!(locals.names(0), ["total", " i0", " limit0", "x"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |src| compile(src).unwrap_err();
+        assert_eq!(
+            error("1 2 frob"),
+            ForthError {
+                line: 1,
+                column: 5,
+                kind: ForthErrorKind::UndefinedWord(String::from("frob"))
+            }
+        );
+        assert_eq!(
+            error("1 then").kind,
+            ForthErrorKind::UnexpectedWord(String::from("then"))
+        );
+        assert_eq!(error("\n: f 1 +").to_string(), "2:1: unterminated `:`");
+        assert_eq!(
+            error("true if 1").kind,
+            ForthErrorKind::Unterminated(String::from("if"))
+        );
+        assert_eq!(error(": f : g ; ;").kind, ForthErrorKind::NestedDefinition);
+        assert_eq!(
+            error("variable x x .").kind,
+            ForthErrorKind::InvalidVariableUse(String::from("x"))
+        );
+        // Top level variables are not visible inside definitions.
+        assert_eq!(
+            error("variable x : f x @ ;").kind,
+            ForthErrorKind::UndefinedWord(String::from("x"))
+        );
+        assert_eq!(
+            error(".\" open").kind,
+            ForthErrorKind::Unterminated(String::from(".\""))
+        );
+        assert_eq!(
+            error("( open").kind,
+            ForthErrorKind::Unterminated(String::from("("))
+        );
+        assert_eq!(
+            error("i").kind,
+            ForthErrorKind::UnexpectedWord(String::from("i"))
+        );
+        assert_eq!(
+            error(":").kind,
+            ForthErrorKind::MissingName(String::from(":"))
+        );
+    }
+}
@@ -1,35 +1,198 @@
 use std::collections::HashMap;
 
-use crate::token::operand::Operand;
+use crate::token::{instruction::Instruction, operand::Operand, Token};
 
+/// Most local slots a frame may have, higher slots are rejected when the
+/// program is decoded.
+pub const MAX_LOCALS: usize = 1 << 16;
+
+/// Local variables and the return address of one function invocation.
+///
+/// Locals live in numbered slots, as many as [`LocalNames`] declares for the
+/// function. Their names are kept apart for debuggers.
 #[derive(Debug, Default)]
 pub struct Frame {
-    variables: HashMap<String, Operand>, // TODO(optimization): Use usize for key
+    locals: Vec<Operand>,
+    /// Address of the function, 0 for the root frame.
+    function: usize,
     return_address: usize,
 }
 
 impl Frame {
-    pub fn new(address: usize) -> Frame {
+    /// A frame for the function at `function` with `size` slots.
+    pub fn new(function: usize, return_address: usize, size: usize) -> Frame {
         Self {
-            variables: Default::default(),
-            return_address: address,
+            locals: vec![Operand::Null; size],
+            function,
+            return_address,
         }
     }
+
+    pub fn function(&self) -> usize {
+        self.function
+    }
+
+    /// Address execution resumes at once the function returns.
     pub fn return_address(&self) -> usize {
         self.return_address
     }
-    pub fn get(&self, var: String) -> Operand {
-        match self.variables.get(&var) {
-            Some(v) => v.clone(),
-            None => Operand::Null,
+
+    /// Value in `slot`, `None` past the size of the frame.
+    pub fn get(&self, slot: usize) -> Option<&Operand> {
+        self.locals.get(slot)
+    }
+
+    pub fn get_mut(&mut self, slot: usize) -> Option<&mut Operand> {
+        self.locals.get_mut(slot)
+    }
+
+    /// Every slot, in order.
+    pub fn locals(&self) -> &[Operand] {
+        &self.locals
+    }
+
+    /// Adds slots up to `size`, for code appended to a running program.
+    pub(crate) fn grow(&mut self, size: usize) {
+        if size > self.locals.len() {
+            self.resize(size);
+        }
+    }
+
+    /// Adds or drops slots so the frame has `size`.
+    pub(crate) fn resize(&mut self, size: usize) {
+        self.locals.resize(size, Operand::Null);
+    }
+}
+
+/// Names and number of the local slots of each function, keyed by its
+/// address.
+///
+/// Functions without their own entry share one table, which
+/// [`LocalNames::resolve`] fills with the names a program loads and stores.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalNames {
+    functions: HashMap<usize, Names>,
+    shared: Names,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Names {
+    by_slot: Vec<String>,
+    slots: HashMap<String, usize>,
+    /// Number of slots, more than `by_slot` when some have no name.
+    size: usize,
+}
+
+impl Names {
+    fn merge(mut self, other: Names) -> Names {
+        let len = self.by_slot.len().max(other.by_slot.len());
+        self.by_slot.resize(len, String::new());
+        for (slot, name) in other.by_slot.into_iter().enumerate() {
+            if !name.is_empty() {
+                self.slots.retain(|_, s| *s != slot);
+                self.slots.insert(name.clone(), slot);
+                self.by_slot[slot] = name;
+            }
+        }
+        for (slot, name) in self.by_slot.iter_mut().enumerate() {
+            if self.slots.get(name.as_str()) != Some(&slot) {
+                name.clear();
+            }
+        }
+        self.size = self.size.max(other.size);
+        self
+    }
+}
+
+impl LocalNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the slots of the function at `function`, slot 0 first.
+    pub fn insert(&mut self, function: usize, names: Vec<String>) {
+        let slots = names.iter().cloned().zip(0..).collect();
+        self.functions.insert(
+            function,
+            Names {
+                size: names.len(),
+                by_slot: names,
+                slots,
+            },
+        );
+    }
+
+    /// The slot names of the function at `function`, empty for slots
+    /// without one.
+    pub fn names(&self, function: usize) -> &[String] {
+        &self.get(function).by_slot
+    }
+
+    pub fn slot(&self, function: usize, name: &str) -> Option<usize> {
+        self.get(function).slots.get(name).copied()
+    }
+
+    /// Number of slots in a frame of the function at `function`.
+    pub fn size(&self, function: usize) -> usize {
+        self.get(function).size
+    }
+
+    /// Adds the names of `other`, which win where both name a slot. A
+    /// function keeps the slots of the names it had, and of those in the
+    /// shared table, so code already resolved against `self` stays valid.
+    pub fn merge(&mut self, other: LocalNames) {
+        for (function, names) in other.functions {
+            let base = self.functions.remove(&function).unwrap_or_else(|| Names {
+                size: self.shared.by_slot.len(),
+                ..self.shared.clone()
+            });
+            self.functions.insert(function, base.merge(names));
         }
+        let shared = std::mem::take(&mut self.shared);
+        self.shared = shared.merge(other.shared);
     }
 
-    pub fn set(&mut self, var: String, val: Operand) {
-        self.variables.insert(var, val);
+    fn get(&self, function: usize) -> &Names {
+        self.functions.get(&function).unwrap_or(&self.shared)
     }
 
-    pub fn values(&self) -> Vec<Operand> {
-        self.variables.values().map(|v| v.clone()).collect()
+    /// Replaces every `load` and `store` of a name in `program` by a
+    /// `loadlocal` or `storelocal` of its slot in the shared table. Names it
+    /// does not have yet get new slots after any that `program` uses
+    /// directly.
+    pub fn resolve(&mut self, program: &mut [Token]) {
+        let names = &mut self.shared;
+        for pair in program.windows(2) {
+            if let [Token::Instruction(Instruction::Loadlocal | Instruction::Storelocal), Token::Data(Operand::Int(slot))] =
+                pair
+            {
+                if (0..MAX_LOCALS as i64).contains(slot) {
+                    names.size = names.size.max(*slot as usize + 1);
+                }
+            }
+        }
+        for n in 1..program.len() {
+            let local = match &program[n - 1] {
+                Token::Instruction(Instruction::Load) => Instruction::Loadlocal,
+                Token::Instruction(Instruction::Store) => Instruction::Storelocal,
+                _ => continue,
+            };
+            let Token::Data(Operand::Str(name)) = &program[n] else {
+                continue;
+            };
+            let slot = match names.slots.get(name) {
+                Some(&slot) => slot,
+                None => {
+                    let slot = names.size;
+                    names.by_slot.resize(slot, String::new());
+                    names.by_slot.push(name.clone());
+                    names.slots.insert(name.clone(), slot);
+                    names.size += 1;
+                    slot
+                }
+            };
+            program[n - 1] = Token::Instruction(local);
+            program[n] = Token::Data(Operand::Int(slot as i64));
+        }
     }
 }
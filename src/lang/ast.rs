@@ -0,0 +1,92 @@
+use crate::token::operand::Operand;
+
+/// Line and column of a node in the source, for errors found after parsing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Pos {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) struct Program {
+    pub functions: Vec<Function>,
+    /// Statements outside of functions, in source order.
+    pub main: Vec<Stmt>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) struct Function {
+    pub name: String,
+    pub pos: Pos,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Stmt {
+    Let {
+        name: String,
+        value: Expr,
+    },
+    Assign {
+        name: String,
+        pos: Pos,
+        value: Expr,
+    },
+    If {
+        cond: Expr,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While {
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum Expr {
+    Literal(Operand),
+    Var {
+        name: String,
+        pos: Pos,
+    },
+    Call {
+        name: String,
+        pos: Pos,
+        args: Vec<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
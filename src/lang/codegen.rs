@@ -0,0 +1,282 @@
+//! Emits svm tokens for a parsed program.
+//!
+//! The main program comes first and ends with `halt`, followed by the
+//! functions. A call pushes the arguments in order, the callee stores them
+//! into its frame from the last to the first and leaves its result on the
+//! stack before `ret`.
+//!
+//! Variables are numbered in the order they are declared in their function
+//! and accessed with `loadlocal` and `storelocal`. Parameters come first.
+
+use std::collections::HashMap;
+
+use super::{
+    ast::{BinaryOp, Expr, Function, Pos, Program, Stmt, UnaryOp},
+    CompileError, CompileErrorKind,
+};
+use crate::{
+    builder::ProgramBuilder,
+    frame::LocalNames,
+    token::{instruction::Instruction, operand::Operand, Token},
+};
+
+/// Built in function that writes its argument and evaluates to it.
+const PRINT: &str = "print";
+
+pub(super) fn generate(program: &Program) -> Result<(Vec<Token>, LocalNames), CompileError> {
+    let mut arities = HashMap::from([(PRINT.to_owned(), 1)]);
+    for f in &program.functions {
+        if arities.insert(f.name.clone(), f.params.len()).is_some() {
+            let kind = CompileErrorKind::DuplicateFunction(f.name.clone());
+            return Err(CompileError::new(f.pos.line, f.pos.column, kind));
+        }
+    }
+    let mut codegen = Codegen {
+        builder: ProgramBuilder::new(),
+        arities,
+        variables: Vec::new(),
+        locals: LocalNames::new(),
+        labels: 0,
+        in_function: false,
+    };
+    codegen.block(&program.main)?;
+    codegen.builder.instr(Instruction::Halt);
+    let main = std::mem::take(&mut codegen.variables);
+    codegen.locals.insert(0, main);
+    for f in &program.functions {
+        codegen.function(f)?;
+    }
+    // Every referenced label is generated here, so building cannot fail.
+    let tokens = (codegen.builder.build()).expect("generated labels are defined");
+    Ok((tokens, codegen.locals))
+}
+
+struct Codegen {
+    builder: ProgramBuilder,
+    arities: HashMap<String, usize>,
+    /// Variables declared so far in the current function or main program,
+    /// by slot.
+    variables: Vec<String>,
+    /// Slot names of the functions generated so far.
+    locals: LocalNames,
+    /// Number of generated labels.
+    labels: usize,
+    in_function: bool,
+}
+
+impl Codegen {
+    /// A fresh label that cannot clash with function names.
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn function(&mut self, f: &Function) -> Result<(), CompileError> {
+        self.in_function = true;
+        self.variables = f.params.clone();
+        let address = self.builder.address();
+        self.builder.label(&f.name);
+        for slot in (0..f.params.len()).rev() {
+            self.store(slot);
+        }
+        self.block(&f.body)?;
+        self.builder.push(Operand::Null).instr(Instruction::Ret);
+        let variables = std::mem::take(&mut self.variables);
+        self.locals.insert(address, variables);
+        Ok(())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), CompileError> {
+        stmts.iter().try_for_each(|stmt| self.statement(stmt))
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Let { name, value } => {
+                self.expression(value)?;
+                let slot = self.declare(name);
+                self.store(slot);
+            }
+            Stmt::Assign { name, pos, value } => {
+                let slot = self.variable(name, *pos)?;
+                self.expression(value)?;
+                self.store(slot);
+            }
+            Stmt::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let (otherwise_label, end) = (self.label(), self.label());
+                self.expression(cond)?;
+                self.builder
+                    .instr(Instruction::Not)
+                    .jump_to(Instruction::Jif, &otherwise_label);
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.builder.jump_to(Instruction::Jmp, &end);
+                }
+                self.builder.label(&otherwise_label);
+                self.block(otherwise)?;
+                self.builder.label(&end);
+            }
+            Stmt::While { cond, body } => {
+                let (start, end) = (self.label(), self.label());
+                self.builder.label(&start);
+                self.expression(cond)?;
+                self.builder
+                    .instr(Instruction::Not)
+                    .jump_to(Instruction::Jif, &end);
+                self.block(body)?;
+                self.builder.jump_to(Instruction::Jmp, &start).label(&end);
+            }
+            Stmt::Return(value) => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => {
+                        self.builder.push(Operand::Null);
+                    }
+                }
+                let i = if self.in_function {
+                    Instruction::Ret
+                } else {
+                    Instruction::Halt
+                };
+                self.builder.instr(i);
+            }
+            Stmt::Expr(e) => {
+                self.expression(e)?;
+                self.builder.instr(Instruction::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, e: &Expr) -> Result<(), CompileError> {
+        match e {
+            Expr::Literal(v) => {
+                self.builder.push(v.clone());
+            }
+            Expr::Var { name, pos } => {
+                let slot = self.variable(name, *pos)?;
+                self.load(slot);
+            }
+            Expr::Call { name, pos, args } => {
+                let error = |kind| Err(CompileError::new(pos.line, pos.column, kind));
+                match self.arities.get(name) {
+                    None => return error(CompileErrorKind::UndefinedFunction(name.clone())),
+                    Some(&arity) if arity != args.len() => {
+                        return error(CompileErrorKind::ArityMismatch {
+                            name: name.clone(),
+                            expected: arity,
+                            found: args.len(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                if name == PRINT {
+                    // `write` leaves the value on the stack.
+                    self.builder.instr(Instruction::Write);
+                } else {
+                    self.builder.jump_to(Instruction::Call, name);
+                }
+            }
+            Expr::Unary { op, expr } => {
+                self.expression(expr)?;
+                match op {
+                    UnaryOp::Neg => self.builder.push(Operand::Int(-1)).instr(Instruction::Mul),
+                    UnaryOp::Not => self.builder.instr(Instruction::Not),
+                };
+            }
+            Expr::Binary { op, lhs, rhs } => self.binary(*op, lhs, rhs)?,
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> Result<(), CompileError> {
+        let (i, swap, negate) = match op {
+            BinaryOp::And | BinaryOp::Or => return self.short_circuit(op, lhs, rhs),
+            BinaryOp::Add => (Instruction::Add, false, false),
+            BinaryOp::Sub => (Instruction::Sub, false, false),
+            BinaryOp::Mul => (Instruction::Mul, false, false),
+            BinaryOp::Div => (Instruction::Div, false, false),
+            BinaryOp::Eq => (Instruction::Iseq, false, false),
+            BinaryOp::Ne => (Instruction::Iseq, false, true),
+            BinaryOp::Gt => (Instruction::Isgt, false, false),
+            BinaryOp::Ge => (Instruction::Isge, false, false),
+            // `a < b` is computed as `b > a`.
+            BinaryOp::Lt => (Instruction::Isgt, true, false),
+            BinaryOp::Le => (Instruction::Isge, true, false),
+        };
+        self.expression(lhs)?;
+        self.expression(rhs)?;
+        if swap {
+            // There is no swap instruction, the operands go through two
+            // hidden variables. Nested expressions are done with them by now.
+            let (first, second) = (self.declare(" rhs"), self.declare(" lhs"));
+            self.store(first);
+            self.store(second);
+            self.load(first);
+            self.load(second);
+        }
+        self.builder.instr(i);
+        if negate {
+            self.builder.instr(Instruction::Not);
+        }
+        Ok(())
+    }
+
+    /// `a && b` keeps `a` if it is false and `a || b` keeps it if it is
+    /// true, otherwise it is replaced by `b`.
+    fn short_circuit(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr) -> Result<(), CompileError> {
+        let end = self.label();
+        self.expression(lhs)?;
+        self.builder.instr(Instruction::Dup);
+        if op == BinaryOp::And {
+            self.builder.instr(Instruction::Not);
+        }
+        self.builder
+            .jump_to(Instruction::Jif, &end)
+            .instr(Instruction::Pop);
+        self.expression(rhs)?;
+        self.builder.label(&end);
+        Ok(())
+    }
+
+    /// The slot of `name`, adding a variable if there is none. Names with a
+    /// leading space are used by the compiler itself and cannot clash with
+    /// declared ones.
+    fn declare(&mut self, name: &str) -> usize {
+        match self.variables.iter().position(|v| v == name) {
+            Some(slot) => slot,
+            None => {
+                self.variables.push(name.to_owned());
+                self.variables.len() - 1
+            }
+        }
+    }
+
+    /// The slot of `name`, which must have been declared before `pos`.
+    fn variable(&self, name: &str, pos: Pos) -> Result<usize, CompileError> {
+        match self.variables.iter().position(|v| v == name) {
+            Some(slot) => Ok(slot),
+            None => {
+                let kind = CompileErrorKind::UndefinedVariable(name.to_owned());
+                Err(CompileError::new(pos.line, pos.column, kind))
+            }
+        }
+    }
+
+    fn load(&mut self, slot: usize) {
+        self.builder
+            .instr_with(Instruction::Loadlocal, Operand::Int(slot as i64));
+    }
+
+    fn store(&mut self, slot: usize) {
+        self.builder
+            .instr_with(Instruction::Storelocal, Operand::Int(slot as i64));
+    }
+}
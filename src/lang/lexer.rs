@@ -0,0 +1,223 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+use super::{CompileError, CompileErrorKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Lexeme {
+    pub line: usize,
+    pub column: usize,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Kind {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Reserved words, e.g. `while`.
+    Keyword(&'static str),
+    /// Punctuation and operators, e.g. `<=`.
+    Symbol(&'static str),
+    Eof,
+}
+
+const KEYWORDS: [&str; 9] = [
+    "fn", "let", "if", "else", "while", "return", "true", "false", "null",
+];
+
+/// Longer symbols first so `<=` wins over `<`.
+const SYMBOLS: [&str; 20] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "<", ">", "+", "-", "*",
+    "/", "!",
+];
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Ident(name) => write!(f, "`{}`", name),
+            Kind::Int(v) => write!(f, "`{}`", v),
+            Kind::Float(v) => write!(f, "`{:?}`", v),
+            Kind::Str(_) => write!(f, "a string"),
+            Kind::Keyword(s) | Kind::Symbol(s) => write!(f, "`{}`", s),
+            Kind::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Splits `src` into lexemes, the last one is always `Kind::Eof`.
+pub(super) fn tokenize(src: &str) -> Result<Vec<Lexeme>, CompileError> {
+    let mut lexer = Lexer {
+        chars: src.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+    let mut lexemes = Vec::new();
+    loop {
+        let lexeme = lexer.next_lexeme()?;
+        let eof = lexeme.kind == Kind::Eof;
+        lexemes.push(lexeme);
+        if eof {
+            return Ok(lexemes);
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, line: usize, column: usize, kind: CompileErrorKind) -> CompileError {
+        CompileError::new(line, column, kind)
+    }
+
+    /// Skips whitespace and `//` comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.chars.peek().copied() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('/') if self.chars.clone().nth(1) == Some('/') => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn next_lexeme(&mut self) -> Result<Lexeme, CompileError> {
+        self.skip_whitespace();
+        let (line, column) = (self.line, self.column);
+        let kind = match self.chars.peek().copied() {
+            None => Kind::Eof,
+            Some('"') => {
+                self.bump();
+                Kind::Str(self.string(line, column)?)
+            }
+            Some(c) if c.is_ascii_digit() => self.number(line, column)?,
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    word.push(c);
+                    self.bump();
+                }
+                match KEYWORDS.iter().find(|k| **k == word) {
+                    Some(k) => Kind::Keyword(k),
+                    None => Kind::Ident(word),
+                }
+            }
+            Some(c) => {
+                let rest: String = self.chars.clone().take(2).collect();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|s| rest.starts_with(**s))
+                    .ok_or_else(|| {
+                        self.error(line, column, CompileErrorKind::UnexpectedCharacter(c))
+                    })?;
+                for _ in 0..symbol.len() {
+                    self.bump();
+                }
+                Kind::Symbol(symbol)
+            }
+        };
+        Ok(Lexeme { line, column, kind })
+    }
+
+    fn number(&mut self, line: usize, column: usize) -> Result<Kind, CompileError> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            let sign = (c == '-' || c == '+') && text.ends_with(['e', 'E']);
+            if !(c.is_ascii_alphanumeric() || c == '.' || sign) {
+                break;
+            }
+            text.push(c);
+            self.bump();
+        }
+        if let Ok(v) = text.parse() {
+            return Ok(Kind::Int(v));
+        }
+        match text.parse() {
+            Ok(v) => Ok(Kind::Float(v)),
+            Err(_) => Err(self.error(line, column, CompileErrorKind::InvalidNumber(text))),
+        }
+    }
+
+    /// Reads a string literal, the opening quote is already consumed.
+    fn string(&mut self, line: usize, column: usize) -> Result<String, CompileError> {
+        let mut s = String::new();
+        loop {
+            let (escape_line, escape_column) = (self.line, self.column);
+            match self.bump() {
+                None => return Err(self.error(line, column, CompileErrorKind::UnterminatedString)),
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(match self.bump() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    Some(c) => {
+                        let kind = CompileErrorKind::InvalidEscape(c);
+                        return Err(self.error(escape_line, escape_column, kind));
+                    }
+                    None => {
+                        return Err(self.error(line, column, CompileErrorKind::UnterminatedString))
+                    }
+                }),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, Kind};
+
+    #[test]
+    fn test_tokenize() {
+        let lexemes = tokenize("let x = 1.5; // comment\nx<=-2 \"a\\n\"").unwrap();
+        let kinds: Vec<_> = lexemes.iter().map(|l| l.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                Kind::Keyword("let"),
+                Kind::Ident(String::from("x")),
+                Kind::Symbol("="),
+                Kind::Float(1.5),
+                Kind::Symbol(";"),
+                Kind::Ident(String::from("x")),
+                Kind::Symbol("<="),
+                Kind::Symbol("-"),
+                Kind::Int(2),
+                Kind::Str(String::from("a\n")),
+                Kind::Eof,
+            ]
+        );
+        assert_eq!((lexemes[5].line, lexemes[5].column), (2, 1));
+        assert_eq!((lexemes[9].line, lexemes[9].column), (2, 7));
+        assert!(tokenize("1x").is_err());
+        assert!(tokenize("\"\\q\"").is_err());
+    }
+}
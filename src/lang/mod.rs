@@ -0,0 +1,333 @@
+//! A small C-like language compiled to svm programs.
+//!
+//! ```text
+//! fn max(a, b) {
+//!     if a >= b {
+//!         return a;
+//!     }
+//!     return b;
+//! }
+//! let x = max(6, 4);
+//! print(x * 2);
+//! return x;
+//! ```
+//!
+//! A source file holds function definitions and the statements of the main
+//! program, in any order. The main program ends at its last statement or at
+//! a `return`, which halts the vm with the returned value on the stack.
+//!
+//! - `let name = expr;` declares a variable, `name = expr;` assigns to one.
+//!   Variables live in the frame of the enclosing function, so functions only
+//!   see their parameters and their own variables.
+//! - `if cond { .. } else if cond { .. } else { .. }` and `while cond { .. }`.
+//! - `fn name(a, b) { .. }` defines a function. Arguments are passed on the
+//!   stack, a function without a `return` returns `null`.
+//! - Expressions use integer, float and string literals, `true`, `false`,
+//!   `null`, calls, `-` `!` `*` `/` `+` `-` `==` `!=` `<` `<=` `>` `>=` and
+//!   the short-circuiting `&&` and `||`, from tightest to loosest binding.
+//!   Operands are evaluated left to right.
+//! - `print(expr)` writes the value with `Instruction::Write` and evaluates
+//!   to it.
+//!
+//! Variables are compiled to numbered slots, [`compile_with_locals`] also
+//! returns their names for [`crate::Vm::with_local_names`].
+
+mod ast;
+mod codegen;
+mod lexer;
+mod parser;
+
+use std::fmt;
+
+use crate::{frame::LocalNames, token::Token};
+
+/// Compiles `src` into a program runnable by [`crate::Vm`].
+pub fn compile(src: &str) -> Result<Vec<Token>, CompileError> {
+    compile_with_locals(src).map(|(program, _)| program)
+}
+
+/// Like [`compile`], also returning the names of each function's variables.
+pub fn compile_with_locals(src: &str) -> Result<(Vec<Token>, LocalNames), CompileError> {
+    let lexemes = lexer::tokenize(src)?;
+    let program = parser::parse(lexemes)?;
+    codegen::generate(&program)
+}
+
+/// A compile error and the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: CompileErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    InvalidEscape(char),
+    InvalidNumber(String),
+    /// The parser wanted `expected` but found `found`.
+    Expected {
+        expected: String,
+        found: String,
+    },
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    DuplicateFunction(String),
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl CompileError {
+    fn new(line: usize, column: usize, kind: CompileErrorKind) -> Self {
+        Self { line, column, kind }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            CompileErrorKind::UnexpectedCharacter(c) => {
+                write!(f, "unexpected character `{}`", c)
+            }
+            CompileErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            CompileErrorKind::InvalidEscape(c) => write!(f, "invalid escape `\\{}`", c),
+            CompileErrorKind::InvalidNumber(n) => write!(f, "invalid number `{}`", n),
+            CompileErrorKind::Expected { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            CompileErrorKind::UndefinedVariable(v) => write!(f, "undefined variable `{}`", v),
+            CompileErrorKind::UndefinedFunction(n) => write!(f, "undefined function `{}`", n),
+            CompileErrorKind::DuplicateFunction(n) => write!(f, "duplicate function `{}`", n),
+            CompileErrorKind::ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} arguments but {} were given",
+                name, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[cfg(test)]
+mod test {
+    use super::{compile, compile_with_locals, CompileError, CompileErrorKind};
+    use crate::{
+        output::Capture,
+        token::{instruction::Instruction, operand::Operand, Token},
+        verify, Vm,
+    };
+
+    fn run(src: &str) -> Vm {
+        let program = compile(src).unwrap();
+        assert_eq!(verify(&program), Ok(()));
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        vm
+    }
+
+    fn error(src: &str) -> CompileError {
+        compile(src).unwrap_err()
+    }
+
+    #[test]
+    fn test_max_ab() {
+        let src = "
+            fn max(a, b) {
+                if a >= b {
+                    return a;
+                }
+                return b;
+            }
+            return max(6, 4);
+        ";
+        let mut vm = run(src);
+        assert!(vm.is_halted());
+        assert_eq!(vm.pop(), Some(Operand::Int(6)));
+        assert_eq!(vm.pop(), None);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let src = "
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            let i = 0;
+            let sum = 0;
+            while i < 5 {
+                i = i + 1;
+                if i == 3 {
+                    sum = sum + 100;
+                } else if i != 4 && !false {
+                    sum = sum + i;
+                } else {
+                    sum = sum - 1;
+                }
+            }
+            return sum * 1000 + fib(10);
+        ";
+        let mut vm = run(src);
+        assert_eq!(
+            vm.pop(),
+            Some(Operand::Int((1 + 2 + 100 - 1 + 5) * 1000 + 55))
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        let cases = [
+            ("1 + 2 * 3", Operand::Int(7)),
+            ("(1 + 2) * 3", Operand::Int(9)),
+            ("10 - 4 - 3", Operand::Int(3)),
+            ("-2.5 * 2", Operand::Float(-5.0)),
+            ("-(1 + 1)", Operand::Int(-2)),
+            ("\"a\\tb\" + \"c\"", Operand::Str(String::from("a\tbc"))),
+            ("1 < 2 && 2 <= 2 && 3 > 2 && 3 >= 3", Operand::Bool(true)),
+            ("false || null == null", Operand::Bool(true)),
+            ("!(1 != 1)", Operand::Bool(true)),
+        ];
+        for (expr, expected) in cases {
+            let mut vm = run(&format!("return {};", expr));
+            assert_eq!(vm.pop(), Some(expected), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_evaluation_order() {
+        let capture = Capture::new();
+        let program = compile(
+            "
+            fn say(v) { print(v); return v; }
+            let a = say(1) < say(2);
+            let b = say(3) <= say(4);
+            print(a && b == (say(5) < say(6) == say(7) < say(8)));
+            ",
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+        vm.run().unwrap();
+        assert_eq!(capture.contents(), "12345678true");
+
+        let mut vm = run("let a = 5; return 1 < a && a <= 5 && !(a < a);");
+        assert_eq!(vm.pop(), Some(Operand::Bool(true)));
+    }
+
+    #[test]
+    fn test_short_circuit_and_print() {
+        let capture = Capture::new();
+        let program = compile(
+            "
+            fn say(v) { print(v); return v; }
+            let a = say(false) && say(1);
+            let b = say(true) || say(2);
+            say(\"x\");
+            print(\"\\n\");
+            f();
+            fn f() {}
+            ",
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+        vm.run().unwrap();
+        assert_eq!(capture.contents(), "falsetruex\n");
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    fn test_locals() {
+        let src = "
+            fn add(a, b) { let c = a + b; return c; }
+            let x = 1;
+            let y = add(x, 2);
+            let x = y;
+        ";
+        let (program, locals) = compile_with_locals(src).unwrap();
+        assert!(!program.iter().any(|t| matches!(
+            t,
+            Token::Instruction(Instruction::Load | Instruction::Store)
+        )));
+        assert_eq!(locals.names(0), ["x", "y"]);
+        let call = program
+            .iter()
+            .position(|t| t == &Token::Instruction(Instruction::Call));
+        match &program[call.unwrap() + 1] {
+            Token::Data(Operand::Int(add)) => {
+                assert_eq!(locals.names(*add as usize), ["a", "b", "c"])
+            }
+            t => panic!("not an address: {:?}", t),
+        }
+
+        let mut vm = Vm::new(program).unwrap().with_local_names(locals);
+        vm.run().unwrap();
+        assert_eq!(vm.variable("x"), Operand::Int(3));
+        assert_eq!(
+            vm.current_frame().locals(),
+            [Operand::Int(3), Operand::Int(3)]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("let x = y;"),
+            CompileError::new(1, 9, CompileErrorKind::UndefinedVariable(String::from("y")))
+        );
+        assert_eq!(
+            error("fn f(a) { return a; }\nf(1, 2);"),
+            CompileError::new(
+                2,
+                1,
+                CompileErrorKind::ArityMismatch {
+                    name: String::from("f"),
+                    expected: 1,
+                    found: 2
+                }
+            )
+        );
+        assert_eq!(
+            error("g();").kind,
+            CompileErrorKind::UndefinedFunction(String::from("g"))
+        );
+        assert_eq!(
+            error("fn f() {}\nfn f() {}"),
+            CompileError::new(2, 4, CompileErrorKind::DuplicateFunction(String::from("f")))
+        );
+        assert_eq!(
+            error("x = 1;").kind,
+            CompileErrorKind::UndefinedVariable(String::from("x"))
+        );
+        // Variables of the caller are not visible in functions.
+        assert_eq!(
+            error("let x = 1;\nfn f() { return x; }").kind,
+            CompileErrorKind::UndefinedVariable(String::from("x"))
+        );
+        assert_eq!(
+            error("let x = 1").to_string(),
+            "1:10: expected `;`, found end of input"
+        );
+        assert_eq!(
+            error("if 1 { fn f() {} }").to_string(),
+            "1:8: expected a statement, found `fn`"
+        );
+        assert_eq!(
+            error("let s = \"abc").kind,
+            CompileErrorKind::UnterminatedString
+        );
+        assert_eq!(
+            error("let x = 1 @ 2;").to_string(),
+            "1:11: unexpected character `@`"
+        );
+    }
+}
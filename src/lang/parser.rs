@@ -0,0 +1,394 @@
+//! Recursive descent parser, one method per precedence level.
+
+use super::{
+    ast::{BinaryOp, Expr, Function, Pos, Program, Stmt, UnaryOp},
+    lexer::{Kind, Lexeme},
+    CompileError, CompileErrorKind,
+};
+use crate::token::operand::Operand;
+
+/// Parses lexemes ending in `Kind::Eof`.
+pub(super) fn parse(lexemes: Vec<Lexeme>) -> Result<Program, CompileError> {
+    let mut parser = Parser { lexemes, next: 0 };
+    let mut program = Program {
+        functions: Vec::new(),
+        main: Vec::new(),
+    };
+    while parser.peek().kind != Kind::Eof {
+        if parser.eat(&Kind::Keyword("fn")) {
+            program.functions.push(parser.function()?);
+        } else {
+            program.main.push(parser.statement()?);
+        }
+    }
+    Ok(program)
+}
+
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Lexeme {
+        &self.lexemes[self.next]
+    }
+
+    fn pos(&self) -> Pos {
+        let lexeme = self.peek();
+        Pos {
+            line: lexeme.line,
+            column: lexeme.column,
+        }
+    }
+
+    fn bump(&mut self) -> Lexeme {
+        let lexeme = self.lexemes[self.next].clone();
+        // Eof stays the next lexeme forever.
+        if lexeme.kind != Kind::Eof {
+            self.next += 1;
+        }
+        lexeme
+    }
+
+    /// Consumes the next lexeme if it is `kind`.
+    fn eat(&mut self, kind: &Kind) -> bool {
+        let found = &self.peek().kind == kind;
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expected(&self, expected: &str) -> CompileError {
+        let lexeme = self.peek();
+        CompileError::new(
+            lexeme.line,
+            lexeme.column,
+            CompileErrorKind::Expected {
+                expected: expected.to_owned(),
+                found: lexeme.kind.to_string(),
+            },
+        )
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), CompileError> {
+        if self.eat(&Kind::Symbol(symbol)) {
+            Ok(())
+        } else {
+            Err(self.expected(&format!("`{}`", symbol)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match &self.peek().kind {
+            Kind::Ident(name) => {
+                let name = name.clone();
+                self.bump();
+                Ok(name)
+            }
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    /// Parses a function after its `fn` keyword.
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let pos = self.pos();
+        let name = self.ident()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if !self.eat(&Kind::Symbol(")")) {
+            loop {
+                params.push(self.ident()?);
+                if self.eat(&Kind::Symbol(")")) {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function {
+            name,
+            pos,
+            params,
+            body,
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Kind::Symbol("}")) {
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let pos = self.pos();
+        let stmt = match self.peek().kind.clone() {
+            Kind::Keyword("let") => {
+                self.bump();
+                let name = self.ident()?;
+                self.expect("=")?;
+                let value = self.expression()?;
+                Stmt::Let { name, value }
+            }
+            Kind::Keyword("if") => {
+                self.bump();
+                return self.if_statement();
+            }
+            Kind::Keyword("while") => {
+                self.bump();
+                let cond = self.expression()?;
+                let body = self.block()?;
+                return Ok(Stmt::While { cond, body });
+            }
+            Kind::Keyword("return") => {
+                self.bump();
+                if self.peek().kind == Kind::Symbol(";") {
+                    Stmt::Return(None)
+                } else {
+                    Stmt::Return(Some(self.expression()?))
+                }
+            }
+            Kind::Ident(name) if self.lexemes[self.next + 1].kind == Kind::Symbol("=") => {
+                self.bump();
+                self.bump();
+                let value = self.expression()?;
+                Stmt::Assign { name, pos, value }
+            }
+            Kind::Keyword("fn") => return Err(self.expected("a statement")),
+            _ => Stmt::Expr(self.expression()?),
+        };
+        self.expect(";")?;
+        Ok(stmt)
+    }
+
+    /// Parses an `if` after its keyword, `else if` chains become nested ifs.
+    fn if_statement(&mut self) -> Result<Stmt, CompileError> {
+        let cond = self.expression()?;
+        let then = self.block()?;
+        let otherwise = if !self.eat(&Kind::Keyword("else")) {
+            Vec::new()
+        } else if self.eat(&Kind::Keyword("if")) {
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If {
+            cond,
+            then,
+            otherwise,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Expr, CompileError> {
+        self.or()
+    }
+
+    /// Parses a left associative chain of the operators in `ops` whose
+    /// operands are parsed by `operand`.
+    fn binary(
+        &mut self,
+        ops: &[(&'static str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, CompileError>,
+    ) -> Result<Expr, CompileError> {
+        let mut lhs = operand(self)?;
+        'outer: loop {
+            for (symbol, op) in ops {
+                if self.eat(&Kind::Symbol(symbol)) {
+                    let rhs = operand(self)?;
+                    lhs = Expr::Binary {
+                        op: *op,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                    };
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, CompileError> {
+        self.binary(&[("||", BinaryOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, CompileError> {
+        self.binary(&[("&&", BinaryOp::And)], Self::equality)
+    }
+
+    fn equality(&mut self) -> Result<Expr, CompileError> {
+        self.binary(
+            &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+            Self::comparison,
+        )
+    }
+
+    fn comparison(&mut self) -> Result<Expr, CompileError> {
+        self.binary(
+            &[
+                ("<=", BinaryOp::Le),
+                (">=", BinaryOp::Ge),
+                ("<", BinaryOp::Lt),
+                (">", BinaryOp::Gt),
+            ],
+            Self::term,
+        )
+    }
+
+    fn term(&mut self) -> Result<Expr, CompileError> {
+        self.binary(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr, CompileError> {
+        self.binary(&[("*", BinaryOp::Mul), ("/", BinaryOp::Div)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let op = if self.eat(&Kind::Symbol("-")) {
+            UnaryOp::Neg
+        } else if self.eat(&Kind::Symbol("!")) {
+            UnaryOp::Not
+        } else {
+            return self.primary();
+        };
+        let expr = self.unary()?;
+        Ok(match (op, expr) {
+            // Fold negative literals so `-1` is a single push.
+            (UnaryOp::Neg, Expr::Literal(Operand::Int(v))) => {
+                Expr::Literal(Operand::Int(v.wrapping_neg()))
+            }
+            (UnaryOp::Neg, Expr::Literal(Operand::Float(v))) => Expr::Literal(Operand::Float(-v)),
+            (op, expr) => Expr::Unary {
+                op,
+                expr: Box::new(expr),
+            },
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let pos = self.pos();
+        let literal = match self.peek().kind.clone() {
+            Kind::Int(v) => Operand::Int(v),
+            Kind::Float(v) => Operand::Float(v),
+            Kind::Str(s) => Operand::Str(s),
+            Kind::Keyword("true") => Operand::Bool(true),
+            Kind::Keyword("false") => Operand::Bool(false),
+            Kind::Keyword("null") => Operand::Null,
+            Kind::Symbol("(") => {
+                self.bump();
+                let expr = self.expression()?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Kind::Ident(name) => {
+                self.bump();
+                if !self.eat(&Kind::Symbol("(")) {
+                    return Ok(Expr::Var { name, pos });
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Kind::Symbol(")")) {
+                    loop {
+                        args.push(self.expression()?);
+                        if self.eat(&Kind::Symbol(")")) {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                return Ok(Expr::Call { name, pos, args });
+            }
+            _ => return Err(self.expected("an expression")),
+        };
+        self.bump();
+        Ok(Expr::Literal(literal))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+    use crate::{
+        lang::{
+            ast::{BinaryOp, Expr, Pos, Stmt},
+            lexer::tokenize,
+        },
+        token::operand::Operand,
+    };
+
+    fn expr(src: &str) -> Expr {
+        let program = parse(tokenize(&format!("{};", src)).unwrap()).unwrap();
+        match program.main.into_iter().next() {
+            Some(Stmt::Expr(e)) => e,
+            stmt => panic!("not an expression: {:?}", stmt),
+        }
+    }
+
+    fn int(v: i64) -> Box<Expr> {
+        Box::new(Expr::Literal(Operand::Int(v)))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            expr("1 - 2 - 3 * -4"),
+            Expr::Binary {
+                op: BinaryOp::Sub,
+                lhs: Box::new(Expr::Binary {
+                    op: BinaryOp::Sub,
+                    lhs: int(1),
+                    rhs: int(2)
+                }),
+                rhs: Box::new(Expr::Binary {
+                    op: BinaryOp::Mul,
+                    lhs: int(3),
+                    rhs: int(-4)
+                }),
+            }
+        );
+        assert_eq!(
+            expr("a < 1 == true || f()"),
+            Expr::Binary {
+                op: BinaryOp::Or,
+                lhs: Box::new(Expr::Binary {
+                    op: BinaryOp::Eq,
+                    lhs: Box::new(Expr::Binary {
+                        op: BinaryOp::Lt,
+                        lhs: Box::new(Expr::Var {
+                            name: String::from("a"),
+                            pos: Pos { line: 1, column: 1 }
+                        }),
+                        rhs: int(1)
+                    }),
+                    rhs: Box::new(Expr::Literal(Operand::Bool(true)))
+                }),
+                rhs: Box::new(Expr::Call {
+                    name: String::from("f"),
+                    pos: Pos {
+                        line: 1,
+                        column: 18
+                    },
+                    args: vec![]
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        let program = parse(
+            tokenize("fn f(a, b) { return; }\nif x { } else if y { y = 1; } else { }").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(program.functions[0].params, ["a", "b"]);
+        assert_eq!(program.functions[0].body, [Stmt::Return(None)]);
+        match &program.main[..] {
+            [Stmt::If { otherwise, .. }] => {
+                assert!(matches!(&otherwise[..], [Stmt::If { then, .. }] if then.len() == 1))
+            }
+            main => panic!("unexpected {:?}", main),
+        }
+    }
+}
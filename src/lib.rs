@@ -1,4 +1,25 @@
+pub mod asm;
+pub mod builder;
+pub mod debugger;
+pub mod disasm;
+mod error;
+pub mod forth;
 mod frame;
+pub mod lang;
+pub mod native;
+pub mod optimize;
+pub mod output;
+pub mod program;
+pub mod repl;
+pub mod syscall;
 pub mod token;
 mod utils;
+pub mod verify;
 mod vm;
+
+pub use error::VmError;
+pub use frame::{Frame, LocalNames};
+pub use optimize::optimize;
+pub use program::Program;
+pub use verify::verify;
+pub use vm::{Vm, VmConfig, VmStats};
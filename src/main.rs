@@ -1,6 +1,222 @@
-use svm::token::operand::Operand;
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-fn main() {
-    let a = Operand::Int(2);
-    println!("Hello, world!");
+use svm::{asm, debugger::Debugger, disasm, repl::Repl, Program, Vm};
+
+const USAGE: &str = "usage: svm <command> [args]
+
+commands:
+    run [--fuel <n>] <file>  run a .svm or .svmb program, optionally
+                             stopping after n instructions
+    asm <file> [-o <out>]    assemble a .svm file into .svmb bytecode
+    disasm <file>            print the assembly listing of a program
+    check <file>             verify a program without running it
+    repl                     evaluate instructions interactively
+    debug <file>             step through a program with breakpoints
+
+exit status: 0 once the program halts, the code given to the exit syscall,
+1 on a runtime error and 2 when the program cannot be loaded, fails
+verification or the arguments are wrong.";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run { path: PathBuf, fuel: Option<u64> },
+    Asm { input: PathBuf, output: PathBuf },
+    Disasm(PathBuf),
+    Check(PathBuf),
+    Repl,
+    Debug(PathBuf),
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = args.split_first().ok_or("missing command")?;
+    let file = |rest: &[String]| match rest {
+        [file] => Ok(PathBuf::from(file)),
+        [] => Err(format!("`{}` expects a file", command)),
+        _ => Err(format!("too many arguments for `{}`", command)),
+    };
+    match command.as_str() {
+        "run" => match rest {
+            [flag, fuel, path] if flag == "--fuel" => Ok(Command::Run {
+                path: PathBuf::from(path),
+                fuel: Some(
+                    fuel.parse()
+                        .map_err(|_| format!("invalid fuel `{}`", fuel))?,
+                ),
+            }),
+            _ => file(rest).map(|path| Command::Run { path, fuel: None }),
+        },
+        "disasm" => file(rest).map(Command::Disasm),
+        "check" => file(rest).map(Command::Check),
+        "repl" if rest.is_empty() => Ok(Command::Repl),
+        "debug" => file(rest).map(Command::Debug),
+        "asm" => match rest {
+            [input] => Ok(Command::Asm {
+                input: PathBuf::from(input),
+                output: Path::new(input).with_extension("svmb"),
+            }),
+            [input, flag, output] if flag == "-o" => Ok(Command::Asm {
+                input: PathBuf::from(input),
+                output: PathBuf::from(output),
+            }),
+            _ => Err(String::from("usage: svm asm <file> [-o <out>]")),
+        },
+        _ => Err(format!("unknown command `{}`", command)),
+    }
+}
+
+/// Reads a program from either bytecode or assembly source, telling them
+/// apart by the bytecode magic header.
+fn load(path: &Path) -> Result<Program, String> {
+    load_with_labels(path).map(|(program, _)| program)
+}
+
+/// Like [`load`], also returning the labels of assembly source.
+fn load_with_labels(path: &Path) -> Result<(Program, HashMap<String, usize>), String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if Program::is_bytecode(&bytes) {
+        return Program::from_bytes(&bytes)
+            .map(|program| (program, HashMap::new()))
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let src = String::from_utf8(bytes)
+        .map_err(|_| format!("{}: neither bytecode nor UTF-8 source", path.display()))?;
+    asm::assemble_with_labels(&src)
+        .map(|(tokens, labels)| (Program::new(tokens), labels))
+        .map_err(|e| format!("{}:{}", path.display(), e))
+}
+
+/// Like [`load`], also rejecting programs that fail [`svm::verify`].
+fn load_verified(path: &Path) -> Result<Program, String> {
+    let program = load(path)?;
+    svm::verify(program.tokens()).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| format!("{}: {}", path.display(), e))
+            .collect::<Vec<_>>()
+            .join("\nsvm: ")
+    })?;
+    Ok(program)
+}
+
+/// A vm for `program`, loaded from `path`.
+fn vm(path: &Path, program: Program) -> Result<Vm, String> {
+    Vm::new(program.into_tokens()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn execute(command: Command) -> Result<ExitCode, String> {
+    match command {
+        Command::Run { path, fuel } => {
+            let mut vm = vm(&path, load_verified(&path)?)?;
+            vm.set_fuel(fuel);
+            if let Err(e) = vm.run() {
+                eprintln!("svm: {}", e);
+                return Ok(ExitCode::from(1));
+            }
+            if let Some(code) = vm.exit_code() {
+                // Like a process exit status only the low byte is kept.
+                return Ok(ExitCode::from(code as u8));
+            }
+        }
+        Command::Asm { input, output } => {
+            let bytes = load(&input)?.to_bytes();
+            fs::write(&output, bytes).map_err(|e| format!("{}: {}", output.display(), e))?;
+        }
+        Command::Disasm(path) => print!("{}", disasm::disassemble(load(&path)?.tokens())),
+        Command::Check(path) => {
+            load_verified(&path)?;
+            println!("{}: ok", path.display());
+        }
+        Command::Debug(path) => {
+            let (program, labels) = load_with_labels(&path)?;
+            Debugger::new(vm(&path, program)?)
+                .with_labels(labels)
+                .run(io::stdin().lock(), io::stdout())
+                .map_err(|e| e.to_string())?;
+        }
+        Command::Repl => {
+            println!("svm repl, :help for commands");
+            Repl::new()
+                .run(io::stdin().lock(), io::stdout())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("svm: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    execute(command).unwrap_or_else(|e| {
+        eprintln!("svm: {}", e);
+        ExitCode::from(2)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{parse_args, Command};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args("run a.svm")),
+            Ok(Command::Run {
+                path: PathBuf::from("a.svm"),
+                fuel: None
+            })
+        );
+        assert_eq!(
+            parse_args(&args("run --fuel 100 a.svm")),
+            Ok(Command::Run {
+                path: PathBuf::from("a.svm"),
+                fuel: Some(100)
+            })
+        );
+        assert!(parse_args(&args("run --fuel x a.svm")).is_err());
+        assert_eq!(
+            parse_args(&args("asm dir/a.svm")),
+            Ok(Command::Asm {
+                input: PathBuf::from("dir/a.svm"),
+                output: PathBuf::from("dir/a.svmb")
+            })
+        );
+        assert_eq!(
+            parse_args(&args("asm a.svm -o b.bin")),
+            Ok(Command::Asm {
+                input: PathBuf::from("a.svm"),
+                output: PathBuf::from("b.bin")
+            })
+        );
+        assert_eq!(
+            parse_args(&args("check a.svmb")),
+            Ok(Command::Check(PathBuf::from("a.svmb")))
+        );
+        assert_eq!(parse_args(&args("repl")), Ok(Command::Repl));
+        assert_eq!(
+            parse_args(&args("debug a.svm")),
+            Ok(Command::Debug(PathBuf::from("a.svm")))
+        );
+        assert!(parse_args(&args("")).is_err());
+        assert!(parse_args(&args("run")).is_err());
+        assert!(parse_args(&args("run a b")).is_err());
+        assert!(parse_args(&args("asm a.svm -x b")).is_err());
+        assert!(parse_args(&args("frob a.svm")).is_err());
+    }
 }
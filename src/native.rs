@@ -0,0 +1,167 @@
+//! Rust functions exposed to programs through `Instruction::Callnative`.
+//!
+//! `callnative` takes the name or the id of a registered function as its
+//! inline operand, pops as many values as the function's arity, calls it with
+//! them in the order they were pushed and pushes the result.
+//!
+//! ```
+//! use svm::token::operand::Operand;
+//! use svm::{asm, Vm, VmError};
+//!
+//! let mut vm = Vm::new(asm::assemble("push 2\npush 3\ncallnative \"pow\"\nhalt").unwrap()).unwrap();
+//! vm.register_native("pow", 2, |args| match args {
+//!     [Operand::Int(base), Operand::Int(exp)] => Ok(Operand::Int(base.pow(*exp as u32))),
+//!     _ => Err(VmError::native("pow expects two integers")),
+//! });
+//! vm.run().unwrap();
+//! assert_eq!(vm.pop(), Some(Operand::Int(8)));
+//! ```
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{token::operand::Operand, VmError};
+
+pub type NativeFn = Rc<dyn Fn(&mut [Operand]) -> Result<Operand, VmError>>;
+
+#[derive(Clone)]
+pub struct Native {
+    pub name: String,
+    /// Number of arguments popped from the stack.
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+/// Native functions by name and by id, ids are handed out in registration
+/// order starting at 0.
+#[derive(Clone, Default)]
+pub struct NativeRegistry {
+    natives: Vec<Native>,
+    ids: HashMap<String, usize>,
+}
+
+impl NativeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `function` under `name` and returns its id. Registering a
+    /// name again replaces the function but keeps the id.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut [Operand]) -> Result<Operand, VmError> + 'static,
+    ) -> usize {
+        let native = Native {
+            name: name.to_owned(),
+            arity,
+            function: Rc::new(function),
+        };
+        match self.ids.get(name) {
+            Some(&id) => {
+                self.natives[id] = native;
+                id
+            }
+            None => {
+                self.ids.insert(name.to_owned(), self.natives.len());
+                self.natives.push(native);
+                self.natives.len() - 1
+            }
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    /// Looks a function up by a `Str` name or an `Int` id.
+    pub fn get(&self, native: &Operand) -> Option<&Native> {
+        match native {
+            Operand::Str(name) => self.natives.get(self.id(name)?),
+            Operand::Int(id) => self.natives.get(usize::try_from(*id).ok()?),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        asm,
+        token::{instruction::Instruction, operand::Operand},
+        Vm, VmError,
+    };
+
+    fn concat(args: &mut [Operand]) -> Result<Operand, VmError> {
+        let mut out = String::new();
+        for arg in args.iter_mut() {
+            match std::mem::replace(arg, Operand::Null) {
+                Operand::Str(s) => out.push_str(&s),
+                _ => return Err(VmError::native("concat expects strings")),
+            }
+        }
+        Ok(Operand::Str(out))
+    }
+
+    #[test]
+    fn test_call_by_name_and_id() {
+        let program = asm::assemble(
+            r#"push "a"
+            push "b"
+            push "c"
+            callnative "concat3"
+            callnative 1
+            halt"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap();
+        assert_eq!(vm.register_native("concat3", 3, concat), 0);
+        assert_eq!(
+            vm.register_native("len", 1, |args| match &args[0] {
+                Operand::Str(s) => Ok(Operand::Int(s.len() as i64)),
+                _ => Err(VmError::native("len expects a string")),
+            }),
+            1
+        );
+        assert_eq!(vm.register_native("concat3", 3, concat), 0);
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Int(3)));
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = Vm::new(asm::assemble("push 1\ncallnative \"concat\"").unwrap()).unwrap();
+        vm.register_native("concat", 2, concat);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackUnderflow {
+                ip: 2,
+                instr: Instruction::Callnative
+            })
+        );
+
+        let mut vm =
+            Vm::new(asm::assemble("push 1\npush 2\ncallnative \"concat\"").unwrap()).unwrap();
+        vm.register_native("concat", 2, concat);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::Native {
+                ip: 4,
+                name: String::from("concat"),
+                message: String::from("concat expects strings")
+            })
+        );
+
+        let mut vm = Vm::new(asm::assemble("callnative \"nope\"").unwrap()).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::UnknownNative {
+                ip: 0,
+                native: Operand::Str(String::from("nope"))
+            })
+        );
+        let mut vm = Vm::new(asm::assemble("callnative 0").unwrap()).unwrap();
+        assert!(matches!(vm.run(), Err(VmError::UnknownNative { .. })));
+    }
+}
@@ -0,0 +1,355 @@
+//! Peephole optimizer for token programs.
+//!
+//! [`optimize`] repeatedly rewrites short instruction sequences until none
+//! is left:
+//!
+//! ```text
+//! push x, pop                   removed
+//! dup, pop                      removed
+//! bool producer, not, not       the `not`s are removed
+//! jmp to the next instruction   removed
+//! push a, not                   push !a
+//! push a, push b, binary op     push (a op b), if it succeeds
+//! ```
+//!
+//! A sequence is only rewritten if no jump lands inside it, and every
+//! `jmp`, `jif` and `call` target is moved along with the code. Programs that
+//! cannot be decoded as instructions followed by their operands, e.g. ones
+//! with `.data` tokens, are returned unchanged.
+
+use std::collections::HashSet;
+
+use crate::{
+    token::{instruction::Instruction, operand::Operand, Token},
+    Vm,
+};
+
+/// Returns an equivalent program with the sequences listed in the module
+/// documentation rewritten.
+pub fn optimize(program: Vec<Token>) -> Vec<Token> {
+    let Some(mut units) = decode(&program) else {
+        return program;
+    };
+    while rewrite(&mut units) {}
+    encode(&units, program.len())
+}
+
+/// One instruction with its operand.
+#[derive(Debug, Clone)]
+struct Unit {
+    /// Address in the original program, or of the first instruction of the
+    /// sequence it replaces.
+    origin: usize,
+    instr: Instruction,
+    operand: Option<Operand>,
+}
+
+impl Unit {
+    fn size(&self) -> usize {
+        1 + usize::from(self.operand.is_some())
+    }
+
+    /// The original address this unit jumps to.
+    fn target(&self) -> Option<usize> {
+        match (&self.operand, self.instr.takes_address()) {
+            (Some(Operand::Int(t)), true) => Some(*t as usize),
+            _ => None,
+        }
+    }
+
+    fn push(&self) -> Option<&Operand> {
+        match self.instr {
+            Instruction::Push => self.operand.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Splits the program into units, `None` if a data token is not an operand
+/// or a jump target is not the start of a unit.
+fn decode(program: &[Token]) -> Option<Vec<Unit>> {
+    let mut units = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let Token::Instruction(instr) = &program[address] else {
+            return None;
+        };
+        let operand = if instr.takes_operand() {
+            match program.get(address + 1)? {
+                Token::Data(v) => Some(v.clone()),
+                Token::Instruction(_) => return None,
+            }
+        } else {
+            None
+        };
+        units.push(Unit {
+            origin: address,
+            instr: instr.clone(),
+            operand,
+        });
+        address += units.last().map_or(1, Unit::size);
+    }
+    let starts: HashSet<usize> = units.iter().map(|u| u.origin).collect();
+    for unit in &units {
+        if unit.instr.takes_address() {
+            match &unit.operand {
+                Some(Operand::Int(t)) if *t >= 0 && starts.contains(&(*t as usize)) => {}
+                _ => return None,
+            }
+        }
+    }
+    Some(units)
+}
+
+/// Index of the unit that now runs when jumping to original address
+/// `target`: the first unit at or after it, since removed units do nothing.
+fn resolve(units: &[Unit], target: usize) -> usize {
+    units.partition_point(|u| u.origin < target)
+}
+
+/// Applies the first rewrite found, returns whether there was one.
+fn rewrite(units: &mut Vec<Unit>) -> bool {
+    // Units that are jumped to, or returned to after a call.
+    let mut entries = HashSet::new();
+    for (n, unit) in units.iter().enumerate() {
+        if let Some(target) = unit.target() {
+            entries.insert(resolve(units, target));
+        }
+        if unit.instr == Instruction::Call {
+            entries.insert(n + 1);
+        }
+    }
+    // Whether units n+1..n+len are only reached from the unit before them.
+    let straight = |n: usize, len: usize| (n + 1..n + len).all(|k| !entries.contains(&k));
+
+    for n in 0..units.len() {
+        let next = |k: usize| units.get(n + k).map(|u| &u.instr);
+        let removed = match (&units[n].instr, next(1), next(2)) {
+            (Instruction::Push | Instruction::Dup, Some(Instruction::Pop), _) if straight(n, 2) => {
+                n..n + 2
+            }
+            (_, Some(Instruction::Not), Some(Instruction::Not))
+                if pushes_bool(&units[n]) && straight(n, 3) =>
+            {
+                n + 1..n + 3
+            }
+            (Instruction::Jmp, _, _)
+                if units[n].target().map(|t| resolve(units, t)) == Some(n + 1) =>
+            {
+                n..n + 1
+            }
+            _ => match fold(units, n) {
+                Some((len, v)) if straight(n, len) => {
+                    units[n] = Unit {
+                        origin: units[n].origin,
+                        instr: Instruction::Push,
+                        operand: Some(v),
+                    };
+                    n + 1..n + len
+                }
+                _ => continue,
+            },
+        };
+        units.drain(removed);
+        return true;
+    }
+    false
+}
+
+/// Whether the unit always leaves a boolean on top of the stack.
+fn pushes_bool(unit: &Unit) -> bool {
+    match unit.instr {
+        Instruction::Iseq | Instruction::Isgt | Instruction::Isge | Instruction::Maphas => true,
+        _ => matches!(unit.push(), Some(Operand::Bool(_))),
+    }
+}
+
+/// The constant computed by the units starting at `n` and how many units
+/// it replaces.
+fn fold(units: &[Unit], n: usize) -> Option<(usize, Operand)> {
+    let a = units[n].push()?;
+    let next = units.get(n + 1)?;
+    if next.instr == Instruction::Not {
+        return match a {
+            Operand::Bool(v) => Some((2, Operand::Bool(!v))),
+            _ => None,
+        };
+    }
+    let b = next.push()?;
+    let op = units.get(n + 2)?.instr.clone();
+    let binary = matches!(
+        op,
+        Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::And
+            | Instruction::Or
+            | Instruction::Iseq
+            | Instruction::Isgt
+            | Instruction::Isge
+    );
+    if !binary {
+        return None;
+    }
+    // Errors such as a division by zero are left to happen at runtime.
+    let v = Vm::execute_binary(units[n + 2].origin, op, a.clone(), b.clone()).ok()?;
+    Some((3, v))
+}
+
+/// Lays the units out again and relocates every jump target.
+fn encode(units: &[Unit], original_len: usize) -> Vec<Token> {
+    let mut addresses = Vec::with_capacity(units.len() + 1);
+    let mut address = 0;
+    for unit in units {
+        addresses.push(address);
+        address += unit.size();
+    }
+    // Jumping past the last unit lands at the end, as before.
+    addresses.push(address);
+
+    let mut program = Vec::with_capacity(original_len);
+    for unit in units {
+        program.push(Token::Instruction(unit.instr.clone()));
+        let operand = match unit.target() {
+            Some(target) => Some(Operand::Int(addresses[resolve(units, target)] as i64)),
+            None => unit.operand.clone(),
+        };
+        program.extend(operand.map(Token::Data));
+    }
+    program
+}
+
+#[cfg(test)]
+mod test {
+    use super::optimize;
+    use crate::{
+        asm::assemble, forth, lang, output::Capture, token::operand::Operand, token::Token, Vm,
+    };
+
+    /// Runs a program and returns its output, final stack and result.
+    fn observe(program: Vec<Token>) -> (String, Vec<Operand>, String) {
+        let capture = Capture::new();
+        let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+        let result = format!("{:?}", vm.run().map_err(|e| e.to_string()));
+        let stack = std::iter::from_fn(|| vm.pop()).collect();
+        (capture.contents(), stack, result)
+    }
+
+    /// Optimizes `program`, checks that it behaves the same and returns it.
+    fn check(program: Vec<Token>) -> Vec<Token> {
+        let optimized = optimize(program.clone());
+        assert!(optimized.len() <= program.len());
+        assert_eq!(observe(optimized.clone()), observe(program));
+        optimized
+    }
+
+    #[test]
+    fn test_rewrites() {
+        let cases = [
+            ("push 1\npush 2\npop\nhalt", "push 1\nhalt"),
+            ("push 1\ndup\npop\nhalt", "push 1\nhalt"),
+            ("push 1\npush 2\niseq\nnot\nnot\nhalt", "push false\nhalt"),
+            ("jmp next\nnext: push 1\nhalt", "push 1\nhalt"),
+            ("push true\nnot\nhalt", "push false\nhalt"),
+            ("push 2\npush 3\nadd\npush 4\nmul\nhalt", "push 20\nhalt"),
+            ("push 1.5\npush 2\nsub\nhalt", "push -0.5\nhalt"),
+            ("push \"a\"\npush \"b\"\nadd\nhalt", "push \"ab\"\nhalt"),
+            ("push NaN\npush NaN\niseq\nhalt", "push false\nhalt"),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(
+                check(assemble(src).unwrap()),
+                assemble(expected).unwrap(),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn test_kept() {
+        let cases = [
+            // Errors are left to the runtime.
+            "push 1\npush 0\ndiv\nhalt",
+            "push 1\npush \"a\"\nsub\nhalt",
+            "push 9223372036854775807\npush 1\nadd\nhalt",
+            // `not` of a value that may not be a boolean.
+            "load \"x\"\nnot\nnot\nhalt",
+            // A jump lands between `push` and `pop`.
+            "push true\njif skip\npush 1\nskip: pop\nhalt",
+        ];
+        for src in cases {
+            let program = assemble(src).unwrap();
+            assert_eq!(check(program.clone()), program, "{}", src);
+        }
+        // Data outside of operands.
+        let program = assemble("jmp start\n.data 1\nstart: push 1\npop\nhalt").unwrap();
+        assert_eq!(optimize(program.clone()), program);
+    }
+
+    #[test]
+    fn test_relocation() {
+        let src = "
+                push 1
+                push 2
+                add
+                call f
+                jmp end
+            f:  push 3
+                pop
+                push 4
+                mul
+                ret
+            end:
+                jmp done
+            done:
+                halt";
+        let optimized = check(assemble(src).unwrap());
+        let expected = "
+                push 3
+                call f
+                jmp end
+            f:  push 4
+                mul
+                ret
+            end:
+                halt";
+        assert_eq!(optimized, assemble(expected).unwrap());
+
+        // A target on a removed instruction moves to the next one.
+        let src = "jmp f\npush 1\nf: push 2\npop\npush 5\nhalt";
+        assert_eq!(
+            check(assemble(src).unwrap()),
+            assemble("jmp f\npush 1\nf: push 5\nhalt").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_generated_code() {
+        let src = "
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            let i = 0;
+            while !(i == 10) {
+                print(fib(i));
+                print(\" \");
+                i = i + 1 * 1;
+            }
+            return 2 * 3 + 4;
+        ";
+        let program = lang::compile(src).unwrap();
+        let optimized = check(program.clone());
+        assert!(optimized.len() < program.len());
+
+        let src = "
+            : fizz? 3 mod 0= dup if .\" Fizz\" then ;
+            : buzz? 5 mod 0= dup if .\" Buzz\" then ;
+            : fizzbuzz dup fizz? over buzz? or if drop else . then ;
+            16 1 do i fizzbuzz cr loop
+        ";
+        check(forth::compile(src).unwrap());
+    }
+}
@@ -0,0 +1,52 @@
+//! Destinations for the output of a [`crate::Vm`].
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// Collects everything written to it, e.g. to check a program's output in
+/// tests. Clones share the same buffer, so keep one and hand the other to
+/// [`crate::Vm::with_output`].
+///
+/// ```
+/// use svm::output::Capture;
+/// use svm::{asm, Vm};
+///
+/// let capture = Capture::new();
+/// let program = asm::assemble("push \"hi\"\npush 0\nsyscall\nhalt").unwrap();
+/// let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+/// vm.run().unwrap();
+/// assert_eq!(capture.contents(), "hi");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buffer.borrow()).into_owned()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.buffer.borrow().clone()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
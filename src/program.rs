@@ -0,0 +1,588 @@
+//! Programs and their compact `.svmb` binary encoding.
+//!
+//! Layout, all integers little endian:
+//!
+//! ```text
+//! magic     b"SVMB"
+//! version   u16
+//! constants u32 count, then per constant a tag byte followed by
+//!           0 = string: u32 byte length and UTF-8 bytes
+//!           1 = float:  u64 bits
+//!           2 = bytes:  u32 length and the bytes
+//! tokens    u32 count, then per token one byte
+//!           0x00..=0x7f instruction opcode
+//!           0x80 null, 0x81 int followed by an i64, 0x82 false, 0x83 true,
+//!           0x84 constant followed by its u32 index,
+//!           0x85 array followed by a u32 length and its elements, each
+//!           encoded like a data token,
+//!           0x86 map followed by a u32 length and its keys and values,
+//!           alternating, in the order of `Operand::sorted_entries`,
+//!           0x87 char followed by its u32 code point
+//! ```
+//!
+//! Version 2 added the bytes constant, the array, map and char tags and the
+//! string and bytes instructions. Each later version adds instructions: 3
+//! `syscall`, 4 `callnative`, 5 `readln`, `read`, `parseint` and
+//! `parsefloat`, 6 `loadlocal` and `storelocal`. Files of older versions are
+//! still read since they only use a subset, opcodes their version did not
+//! have yet are rejected.
+
+use std::{collections::HashMap, fmt};
+
+use crate::token::{
+    instruction::Instruction,
+    operand::{Operand, MAX_NESTING},
+    Token,
+};
+
+pub const MAGIC: &[u8; 4] = b"SVMB";
+pub const VERSION: u16 = 6;
+
+/// Number of opcodes in each version, `OPCODES[v - 1]` for version `v`.
+/// Instructions are only ever appended, so a version knows every opcode
+/// below its count.
+const OPCODES: [u8; VERSION as usize] = [33, 38, 39, 40, 44, 46];
+
+const TAG_NULL: u8 = 0x80;
+const TAG_INT: u8 = 0x81;
+const TAG_FALSE: u8 = 0x82;
+const TAG_TRUE: u8 = 0x83;
+const TAG_CONSTANT: u8 = 0x84;
+const TAG_ARRAY: u8 = 0x85;
+const TAG_MAP: u8 = 0x86;
+const TAG_CHAR: u8 = 0x87;
+
+const CONSTANT_STR: u8 = 0;
+const CONSTANT_FLOAT: u8 = 1;
+const CONSTANT_BYTES: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    tokens: Vec<Token>,
+}
+
+impl Program {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens }
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
+
+    /// Whether `bytes` starts with the `.svmb` magic header.
+    pub fn is_bytecode(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut constants = ConstantPool::default();
+        let mut code = Vec::new();
+        for token in &self.tokens {
+            match token {
+                Token::Instruction(i) => code.push(i.opcode()),
+                Token::Data(v) => encode_operand(v, &mut constants, &mut code),
+            }
+        }
+
+        let mut bytes = Vec::from(&MAGIC[..]);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(constants.entries.len() as u32).to_le_bytes());
+        for constant in &constants.entries {
+            match constant {
+                Operand::Str(s) => {
+                    bytes.push(CONSTANT_STR);
+                    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(s.as_bytes());
+                }
+                Operand::Float(v) => {
+                    bytes.push(CONSTANT_FLOAT);
+                    bytes.extend_from_slice(&v.to_bits().to_le_bytes());
+                }
+                Operand::Bytes(b) => {
+                    bytes.push(CONSTANT_BYTES);
+                    bytes.extend_from_slice(&(b.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(b);
+                }
+                _ => unreachable!("only strings, floats and bytes are pooled"),
+            }
+        }
+        bytes.extend_from_slice(&(self.tokens.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&code);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, BytecodeError> {
+        let mut r = Reader { bytes, offset: 0 };
+        if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = r.u16()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let opcodes = OPCODES[version as usize - 1];
+
+        let count = r.u32()?;
+        let mut constants = Vec::new();
+        for _ in 0..count {
+            let offset = r.offset;
+            let constant = match r.u8()? {
+                CONSTANT_STR => {
+                    let len = r.u32()? as usize;
+                    let s = r.take(len)?;
+                    let s = String::from_utf8(s.to_vec())
+                        .map_err(|_| BytecodeError::InvalidUtf8 { offset })?;
+                    Operand::Str(s)
+                }
+                CONSTANT_FLOAT => Operand::Float(f64::from_bits(r.u64()?)),
+                CONSTANT_BYTES => {
+                    let len = r.u32()? as usize;
+                    Operand::Bytes(r.take(len)?.to_vec())
+                }
+                tag => return Err(BytecodeError::InvalidTag { offset, tag }),
+            };
+            constants.push(constant);
+        }
+
+        let count = r.u32()?;
+        let mut tokens = Vec::new();
+        for _ in 0..count {
+            let offset = r.offset;
+            let token = match r.u8()? {
+                tag if tag >= TAG_NULL => Token::Data(decode_operand(&mut r, &constants, tag, 0)?),
+                tag => Token::Instruction(
+                    Instruction::from_opcode(tag)
+                        .filter(|_| tag < opcodes)
+                        .ok_or(BytecodeError::InvalidTag { offset, tag })?,
+                ),
+            };
+            tokens.push(token);
+        }
+        if r.offset != bytes.len() {
+            return Err(BytecodeError::TrailingBytes { offset: r.offset });
+        }
+        Ok(Program { tokens })
+    }
+}
+
+impl From<Vec<Token>> for Program {
+    fn from(tokens: Vec<Token>) -> Self {
+        Self::new(tokens)
+    }
+}
+
+fn encode_operand(v: &Operand, constants: &mut ConstantPool, code: &mut Vec<u8>) {
+    match v {
+        Operand::Null => code.push(TAG_NULL),
+        Operand::Int(v) => {
+            code.push(TAG_INT);
+            code.extend_from_slice(&v.to_le_bytes());
+        }
+        Operand::Bool(false) => code.push(TAG_FALSE),
+        Operand::Bool(true) => code.push(TAG_TRUE),
+        Operand::Char(c) => {
+            code.push(TAG_CHAR);
+            code.extend_from_slice(&(*c as u32).to_le_bytes());
+        }
+        Operand::Str(_) | Operand::Float(_) | Operand::Bytes(_) => {
+            code.push(TAG_CONSTANT);
+            code.extend_from_slice(&constants.index(v).to_le_bytes());
+        }
+        Operand::Array(elements) => {
+            code.push(TAG_ARRAY);
+            code.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+            for element in elements {
+                encode_operand(element, constants, code);
+            }
+        }
+        Operand::Map(map) => {
+            code.push(TAG_MAP);
+            code.extend_from_slice(&(map.len() as u32).to_le_bytes());
+            for (key, value) in Operand::sorted_entries(map) {
+                encode_operand(key, constants, code);
+                encode_operand(value, constants, code);
+            }
+        }
+    }
+}
+
+/// Decodes a data token whose `tag` byte was just read, inside `depth`
+/// arrays or maps.
+fn decode_operand(
+    r: &mut Reader,
+    constants: &[Operand],
+    tag: u8,
+    depth: usize,
+) -> Result<Operand, BytecodeError> {
+    let offset = r.offset - 1;
+    if matches!(tag, TAG_ARRAY | TAG_MAP) && depth == MAX_NESTING {
+        return Err(BytecodeError::TooDeep { offset });
+    }
+    let v = match tag {
+        TAG_NULL => Operand::Null,
+        TAG_INT => Operand::Int(r.u64()? as i64),
+        TAG_FALSE => Operand::Bool(false),
+        TAG_TRUE => Operand::Bool(true),
+        TAG_CONSTANT => {
+            let index = r.u32()?;
+            constants
+                .get(index as usize)
+                .ok_or(BytecodeError::InvalidConstant { offset, index })?
+                .clone()
+        }
+        TAG_CHAR => {
+            Operand::Char(char::from_u32(r.u32()?).ok_or(BytecodeError::InvalidChar { offset })?)
+        }
+        TAG_ARRAY => {
+            let len = r.u32()?;
+            let mut elements = Vec::new();
+            for _ in 0..len {
+                let tag = r.u8()?;
+                elements.push(decode_operand(r, constants, tag, depth + 1)?);
+            }
+            Operand::Array(elements)
+        }
+        TAG_MAP => {
+            let len = r.u32()?;
+            let mut map = HashMap::new();
+            for _ in 0..len {
+                let tag = r.u8()?;
+                let key = decode_operand(r, constants, tag, depth + 1)?;
+                let tag = r.u8()?;
+                map.insert(key, decode_operand(r, constants, tag, depth + 1)?);
+            }
+            Operand::Map(map)
+        }
+        tag => return Err(BytecodeError::InvalidTag { offset, tag }),
+    };
+    Ok(v)
+}
+
+#[derive(Default)]
+struct ConstantPool {
+    entries: Vec<Operand>,
+    strings: HashMap<String, u32>,
+    floats: HashMap<u64, u32>,
+    bytes: HashMap<Vec<u8>, u32>,
+}
+
+impl ConstantPool {
+    fn index(&mut self, v: &Operand) -> u32 {
+        let next = self.entries.len() as u32;
+        let index = match v {
+            Operand::Str(s) => *self.strings.entry(s.clone()).or_insert(next),
+            Operand::Float(f) => *self.floats.entry(f.to_bits()).or_insert(next),
+            Operand::Bytes(b) => *self.bytes.entry(b.clone()).or_insert(next),
+            _ => unreachable!("only strings, floats and bytes are pooled"),
+        };
+        if index == next {
+            self.entries.push(v.clone());
+        }
+        index
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self
+            .offset
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(BytecodeError::Truncated {
+                offset: self.bytes.len(),
+            })?;
+        let v = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Why a `.svmb` file could not be loaded. Offsets are byte positions in the
+/// file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    BadMagic,
+    /// The file was written by a newer (or unknown) version of the format.
+    UnsupportedVersion(u16),
+    /// The file ended in the middle of an entry.
+    Truncated {
+        offset: usize,
+    },
+    InvalidTag {
+        offset: usize,
+        tag: u8,
+    },
+    InvalidConstant {
+        offset: usize,
+        index: u32,
+    },
+    InvalidUtf8 {
+        offset: usize,
+    },
+    /// A char token holds a value that is not a Unicode scalar value.
+    InvalidChar {
+        offset: usize,
+    },
+    /// Arrays and maps are nested deeper than [`MAX_NESTING`].
+    TooDeep {
+        offset: usize,
+    },
+    TrailingBytes {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::BadMagic => write!(f, "not an svm bytecode file"),
+            BytecodeError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported bytecode version {}, expected 1 to {}",
+                v, VERSION
+            ),
+            BytecodeError::Truncated { offset } => {
+                write!(f, "file is truncated at byte {}", offset)
+            }
+            BytecodeError::InvalidTag { offset, tag } => {
+                write!(f, "invalid tag {:#04x} at byte {}", tag, offset)
+            }
+            BytecodeError::InvalidConstant { offset, index } => {
+                write!(f, "invalid constant index {} at byte {}", index, offset)
+            }
+            BytecodeError::InvalidUtf8 { offset } => {
+                write!(f, "constant at byte {} is not valid UTF-8", offset)
+            }
+            BytecodeError::InvalidChar { offset } => {
+                write!(f, "invalid char at byte {}", offset)
+            }
+            BytecodeError::TooDeep { offset } => {
+                write!(f, "operand nested too deeply at byte {}", offset)
+            }
+            BytecodeError::TrailingBytes { offset } => {
+                write!(f, "unexpected data after the last token at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError {}
+
+#[cfg(test)]
+mod test {
+    use super::{BytecodeError, Program, MAGIC, OPCODES, VERSION};
+    use crate::{
+        tbool, tfloat, tint,
+        token::{
+            instruction::*,
+            operand::{Operand, MAX_NESTING},
+            Token,
+        },
+        tstr,
+    };
+
+    fn program() -> Program {
+        Program::new(vec![
+            PUSH,
+            tint!(-42),
+            PUSH,
+            tfloat!(2.5),
+            PUSH,
+            tstr!(String::from("héllo")),
+            PUSH,
+            tstr!(String::from("héllo")),
+            PUSH,
+            tbool!(true),
+            PUSH,
+            tbool!(false),
+            PUSH,
+            Token::Data(Operand::Null),
+            PUSH,
+            Token::Data(Operand::Array(vec![
+                Operand::Int(1),
+                Operand::Array(vec![Operand::Str(String::from("héllo"))]),
+                Operand::Null,
+            ])),
+            PUSH,
+            Token::Data(Operand::Map(
+                [
+                    (Operand::Str(String::from("k")), Operand::Float(2.5)),
+                    (Operand::Int(3), Operand::Array(vec![])),
+                    (Operand::Char('é'), Operand::Bytes(vec![0, 0xff])),
+                ]
+                .into_iter()
+                .collect(),
+            )),
+            JMP,
+            tint!(0),
+            HALT,
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = program().to_bytes();
+        assert!(Program::is_bytecode(&bytes));
+        assert_eq!(Program::from_bytes(&bytes), Ok(program()));
+    }
+
+    #[test]
+    fn test_constants_are_shared() {
+        let once = Program::new(vec![PUSH, tstr!(String::from("abc"))]).to_bytes();
+        let twice = Program::new(vec![
+            PUSH,
+            tstr!(String::from("abc")),
+            PUSH,
+            tstr!(String::from("abc")),
+        ])
+        .to_bytes();
+        assert_eq!(twice.len(), once.len() + 2 + 4);
+    }
+
+    #[test]
+    fn test_version() {
+        let mut bytes = program().to_bytes();
+        assert_eq!(bytes[4..6], [6, 0]);
+        bytes[4] = 1;
+        assert_eq!(Program::from_bytes(&bytes), Ok(program()));
+        bytes[4] = 7;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::UnsupportedVersion(7))
+        );
+    }
+
+    #[test]
+    fn test_opcodes() {
+        // Adding an instruction needs a new version.
+        assert_eq!(
+            OPCODES[VERSION as usize - 1] as usize,
+            Instruction::ALL.len()
+        );
+        let mut bytes = Program::new(vec![CALLNATIVE, tint!(0)]).to_bytes();
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Ok(Program::new(vec![CALLNATIVE, tint!(0)]))
+        );
+        bytes[4] = 3;
+        let offset = bytes.len() - 10;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::InvalidTag {
+                offset,
+                tag: Instruction::Callnative.opcode()
+            })
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = program().to_bytes();
+        for len in MAGIC.len()..bytes.len() {
+            assert!(
+                matches!(
+                    Program::from_bytes(&bytes[..len]),
+                    Err(BytecodeError::Truncated { .. })
+                ),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        assert_eq!(
+            Program::from_bytes(b"ELF\x7f"),
+            Err(BytecodeError::BadMagic)
+        );
+
+        let mut bytes = program().to_bytes();
+        bytes[4] = 99;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::UnsupportedVersion(99))
+        );
+
+        let mut bytes = Program::new(vec![HALT]).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] = 0x7f;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::InvalidTag {
+                offset: last,
+                tag: 0x7f
+            })
+        );
+
+        // Array elements must be data, not opcodes.
+        let mut bytes =
+            Program::new(vec![Token::Data(Operand::Array(vec![Operand::Null]))]).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] = 0x00;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::InvalidTag {
+                offset: last,
+                tag: 0x00
+            })
+        );
+
+        let mut bytes = Program::new(vec![Token::Data(Operand::Char('a'))]).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] = 0xd8;
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::InvalidChar { offset: last - 4 })
+        );
+
+        // 0 tokens, then one made of many nested arrays.
+        let mut bytes = Program::new(vec![]).to_bytes();
+        let count = bytes.len() - 4;
+        bytes[count] = 1;
+        let nested = 200_000;
+        for _ in 0..nested {
+            bytes.push(0x85);
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+        }
+        assert_eq!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::TooDeep {
+                offset: count + 4 + 5 * MAX_NESTING
+            })
+        );
+
+        let mut bytes = Program::new(vec![HALT]).to_bytes();
+        bytes.push(0);
+        assert!(matches!(
+            Program::from_bytes(&bytes),
+            Err(BytecodeError::TrailingBytes { .. })
+        ));
+    }
+}
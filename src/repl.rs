@@ -0,0 +1,239 @@
+//! Interactive read-eval-print loop over a single live [`Vm`].
+//!
+//! Every input line is assembled, appended to the program and executed right
+//! away. Lines starting with `:` are meta-commands, see [`HELP`]. Labels are
+//! only visible within the line (or `:load`ed file) that defines them.
+//!
+//! When an instruction fails, the stack is put back as it was before that
+//! instruction and the rest of the line is skipped.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use crate::{asm, token::Token, Vm};
+
+pub const HELP: &str = ":stack        show the operand stack, top last
+:frame        show the variables of the current frame
+:reset        start over with an empty vm
+:load <file>  assemble a file and run it
+:help         show this help
+:quit         leave the repl";
+
+pub struct Repl {
+    vm: Vm,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            vm: Vm::new(Vec::new()).expect("an empty program decodes"),
+        }
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    /// Reads lines from `input` until it ends or `:quit`, writing prompts and
+    /// results to `output`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            if matches!(line.trim(), ":quit" | ":q") {
+                break;
+            }
+            match self.eval(&line) {
+                Ok(out) => write!(output, "{}", out)?,
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates one line and returns the text to show for it.
+    pub fn eval(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        match line.split_once(char::is_whitespace).unwrap_or((line, "")) {
+            (":stack", _) => Ok(self.stack()),
+            (":frame", _) => Ok(self.frame()),
+            (":reset", _) => {
+                *self = Repl::new();
+                Ok(String::new())
+            }
+            (":help", _) => Ok(format!("{}\n", HELP)),
+            (":load", path) if !path.trim().is_empty() => {
+                let path = path.trim();
+                let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                let tokens = asm::assemble_at(&src, self.vm.program().len())
+                    .map_err(|e| format!("{}:{}", path, e))?;
+                self.execute(tokens)
+            }
+            (command, _) if command.starts_with(':') => {
+                Err(format!("unknown command `{}`, try :help", command))
+            }
+            _ => {
+                let tokens =
+                    asm::assemble_at(line, self.vm.program().len()).map_err(|e| e.to_string())?;
+                self.execute(tokens)
+            }
+        }
+    }
+
+    fn execute(&mut self, tokens: Vec<Token>) -> Result<String, String> {
+        if tokens.is_empty() {
+            return Ok(String::new());
+        }
+        if self.vm.is_halted() {
+            return Err(String::from("the vm has halted, use :reset to start over"));
+        }
+        self.vm.extend_program(tokens).map_err(|e| e.to_string())?;
+        while !self.vm.is_halted() && self.vm.ip() < self.vm.program().len() {
+            let stack = self.vm.stack().clone();
+            if let Err(e) = self.vm.step() {
+                // Give the operands back and skip whatever is left of the
+                // faulting input.
+                while self.vm.pop().is_some() {}
+                for v in stack.into_iter().rev() {
+                    if let Token::Data(v) = v {
+                        self.vm.push(v);
+                    }
+                }
+                self.vm.set_ip(self.vm.program().len());
+                return Err(e.to_string());
+            }
+        }
+        Ok(format!("ip={} {}", self.vm.ip(), self.stack()))
+    }
+
+    fn stack(&self) -> String {
+        let values: Vec<String> = self
+            .vm
+            .stack()
+            .iter()
+            .rev()
+            .map(|v| format!("{:#}", v))
+            .collect();
+        format!("stack: [{}]\n", values.join(", "))
+    }
+
+    fn frame(&self) -> String {
+        let frame = self.vm.current_frame();
+        let mut out = format!(
+            "depth={} return_address={}\n",
+            self.vm.frames().count() - 1,
+            frame.return_address()
+        );
+        for (name, value) in self.vm.variables() {
+            let _ = writeln!(out, "  {} = {:#}", name, value);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::Repl;
+
+    #[test]
+    fn test_eval() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval("push 3"), Ok(String::from("ip=2 stack: [3]\n")));
+        assert_eq!(
+            repl.eval("push 4"),
+            Ok(String::from("ip=4 stack: [3, 4]\n"))
+        );
+        assert_eq!(repl.eval("add"), Ok(String::from("ip=5 stack: [7]\n")));
+        assert_eq!(repl.eval("; just a comment"), Ok(String::new()));
+        assert_eq!(
+            repl.eval("store \"x\""),
+            Ok(String::from("ip=7 stack: []\n"))
+        );
+        assert_eq!(
+            repl.eval(":frame"),
+            Ok(String::from("depth=0 return_address=0\n  x = 7\n"))
+        );
+    }
+
+    #[test]
+    fn test_errors_keep_state() {
+        let mut repl = Repl::new();
+        repl.eval("push 1").unwrap();
+        assert!(repl.eval("frob").is_err());
+        assert!(repl.eval("push \"a\"").is_ok());
+        assert_eq!(
+            repl.eval("add"),
+            Err(String::from(
+                "type mismatch at 4: cannot apply add to 1 and \"a\""
+            ))
+        );
+        assert_eq!(repl.eval(":stack"), Ok(String::from("stack: [1, \"a\"]\n")));
+        assert_eq!(
+            repl.eval("push 2\npop\npop\nadd\npush 3"),
+            Err(String::from("stack underflow at 9: add needs more values"))
+        );
+        assert_eq!(repl.eval(":stack"), Ok(String::from("stack: [1]\n")));
+        assert_eq!(
+            repl.eval("push 2"),
+            Ok(String::from("ip=14 stack: [1, 2]\n"))
+        );
+        assert!(repl.eval(":nope").is_err());
+        // Code that cannot be decoded is not added.
+        let len = repl.vm().program().len();
+        assert_eq!(
+            repl.eval(".data 1"),
+            Err(String::from("cannot execute data at 14"))
+        );
+        assert_eq!(repl.vm().program().len(), len);
+    }
+
+    #[test]
+    fn test_halt_and_reset() {
+        let mut repl = Repl::new();
+        repl.eval("push 1").unwrap();
+        repl.eval("halt").unwrap();
+        assert!(repl.eval("push 2").is_err());
+        repl.eval(":reset").unwrap();
+        assert!(repl.vm().program().is_empty());
+        assert_eq!(repl.eval("push 2"), Ok(String::from("ip=2 stack: [2]\n")));
+    }
+
+    #[test]
+    fn test_load() {
+        let path =
+            std::env::temp_dir().join(format!("svm_repl_test_load_{}.svm", std::process::id()));
+        std::fs::write(
+            &path,
+            "jmp main\ndouble: push 2\nmul\nret\nmain: push 5\ncall double",
+        )
+        .unwrap();
+        let mut repl = Repl::new();
+        repl.eval("push 1").unwrap();
+        repl.eval(&format!(":load {}", path.display())).unwrap();
+        assert_eq!(repl.eval(":stack"), Ok(String::from("stack: [1, 10]\n")));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run() {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        repl.run(Cursor::new("push 1\n:quit\npush 2\n"), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> ip=2 stack: [1]\n> ");
+    }
+}
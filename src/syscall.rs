@@ -0,0 +1,260 @@
+//! Numbered system calls reached through `Instruction::Syscall`.
+//!
+//! `syscall` pops the id from the stack and runs the handler registered for
+//! it in the vm's [`SyscallTable`]. The handler pops its own arguments with
+//! [`arg`] and pushes its results with [`ret`]. The standard table provides:
+//!
+//! ```text
+//! id  name          stack effect
+//! 0   WRITE_STDOUT  ( value -- )  to the vm's output, stdout by default
+//! 1   WRITE_STDERR  ( value -- )
+//! 2   READ_LINE     ( -- line )   from the vm's input, null at the end
+//! 3   EXIT          ( code -- )   halts, see `Vm::exit_code`
+//! 4   CLOCK         ( -- millis ) milliseconds since the Unix epoch
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    token::{instruction::Instruction, operand::Operand},
+    Vm, VmError,
+};
+
+pub const WRITE_STDOUT: i64 = 0;
+pub const WRITE_STDERR: i64 = 1;
+pub const READ_LINE: i64 = 2;
+pub const EXIT: i64 = 3;
+pub const CLOCK: i64 = 4;
+
+/// A syscall implementation. `ip` is the address of the `syscall`
+/// instruction, to be used in the errors it returns.
+pub type Handler = Rc<dyn Fn(&mut Vm, usize) -> Result<(), VmError>>;
+
+#[derive(Clone, Default)]
+pub struct SyscallTable {
+    handlers: HashMap<i64, Handler>,
+}
+
+impl SyscallTable {
+    /// A table without any syscalls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The syscalls listed in the module documentation.
+    pub fn standard() -> Self {
+        let mut table = Self::new();
+        table.register(WRITE_STDOUT, |vm, ip| {
+            let v = arg(vm, ip)?;
+            write_value(vm.output(), &v).map_err(|e| io_error(ip, e))
+        });
+        table.register(WRITE_STDERR, |vm, ip| {
+            let v = arg(vm, ip)?;
+            write_value(&mut io::stderr(), &v).map_err(|e| io_error(ip, e))
+        });
+        table.register(READ_LINE, |vm, ip| {
+            let line = vm.read_line().map_err(|e| io_error(ip, e))?;
+            ret(vm, ip, line.map_or(Operand::Null, Operand::Str))
+        });
+        table.register(EXIT, |vm, ip| match arg(vm, ip)? {
+            Operand::Int(code) => {
+                vm.exit(code);
+                Ok(())
+            }
+            operand => Err(VmError::InvalidOperand {
+                ip,
+                instr: Instruction::Syscall,
+                operand,
+            }),
+        });
+        table.register(CLOCK, |vm, ip| {
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as i64);
+            ret(vm, ip, Operand::Int(millis))
+        });
+        table
+    }
+
+    /// Installs `handler` as syscall `id`, replacing any previous one.
+    pub fn register(
+        &mut self,
+        id: i64,
+        handler: impl Fn(&mut Vm, usize) -> Result<(), VmError> + 'static,
+    ) {
+        self.handlers.insert(id, Rc::new(handler));
+    }
+
+    /// Removes syscall `id`, so calling it fails with
+    /// `VmError::UnknownSyscall`. Returns whether it was registered.
+    pub fn disable(&mut self, id: i64) -> bool {
+        self.handlers.remove(&id).is_some()
+    }
+
+    pub fn get(&self, id: i64) -> Option<Handler> {
+        self.handlers.get(&id).cloned()
+    }
+}
+
+/// Pops an argument of the syscall at `ip`.
+pub fn arg(vm: &mut Vm, ip: usize) -> Result<Operand, VmError> {
+    vm.pop().ok_or(VmError::StackUnderflow {
+        ip,
+        instr: Instruction::Syscall,
+    })
+}
+
+/// Pushes a result of the syscall at `ip`, failing with
+/// `VmError::StackOverflow` past the vm's stack limit.
+pub fn ret(vm: &mut Vm, ip: usize, v: Operand) -> Result<(), VmError> {
+    vm.push_checked(ip, v)
+}
+
+fn io_error(ip: usize, e: io::Error) -> VmError {
+    VmError::Io {
+        ip,
+        message: e.to_string(),
+    }
+}
+
+/// Writes bytes as they are and anything else in its display form.
+fn write_value(out: &mut (impl Write + ?Sized), v: &Operand) -> io::Result<()> {
+    match v {
+        Operand::Bytes(b) => out.write_all(b)?,
+        v => write!(out, "{}", v)?,
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SyscallTable, CLOCK, EXIT, WRITE_STDOUT};
+    use crate::{asm, output::Capture, token::operand::Operand, Vm, VmConfig, VmError};
+
+    #[test]
+    fn test_exit() {
+        let mut vm = Vm::new(asm::assemble("push 7\npush 3\nsyscall\npush 1").unwrap()).unwrap();
+        vm.run().unwrap();
+        assert!(vm.is_halted());
+        assert_eq!(vm.exit_code(), Some(7));
+        assert!(vm.stack().is_empty());
+
+        let mut vm = Vm::new(asm::assemble("push 0\nhalt").unwrap()).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.exit_code(), None);
+    }
+
+    #[test]
+    fn test_write() {
+        let capture = Capture::new();
+        let program = asm::assemble(
+            r#"push "a\n"
+            push 0
+            syscall
+            push 'b'
+            push 0
+            syscall
+            push b"c"
+            push 0
+            syscall
+            push 4
+            push 0
+            syscall
+            halt"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap().with_output(capture.clone());
+        vm.run().unwrap();
+        assert_eq!(capture.contents(), "a\nbc4");
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let config = VmConfig {
+            max_stack_size: Some(2),
+            ..VmConfig::default()
+        };
+        let program = asm::assemble("push 9\nsyscall\nhalt").unwrap();
+        let mut vm = Vm::with_config(program, config).unwrap();
+        vm.syscalls_mut().register(9, |vm, ip| {
+            for n in 0..3 {
+                super::ret(vm, ip, Operand::Int(n))?;
+            }
+            Ok(())
+        });
+        assert_eq!(vm.run(), Err(VmError::StackOverflow { ip: 2, limit: 2 }));
+        assert_eq!(vm.stack().len(), 2);
+    }
+
+    #[test]
+    fn test_read_line() {
+        let program = asm::assemble("push 2\nsyscall\npush 2\nsyscall\nhalt").unwrap();
+        let mut vm = Vm::new(program)
+            .unwrap()
+            .with_input(std::io::Cursor::new("only line\n"));
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Null));
+        assert_eq!(vm.pop(), Some(Operand::Str(String::from("only line"))));
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut vm =
+            Vm::new(asm::assemble(&format!("push {}\nsyscall\nhalt", CLOCK)).unwrap()).unwrap();
+        vm.run().unwrap();
+        assert!(matches!(vm.pop(), Some(Operand::Int(millis)) if millis > 0));
+    }
+
+    #[test]
+    fn test_replace_and_disable() {
+        let program = asm::assemble("push 20\npush 0\nsyscall\nhalt").unwrap();
+        let mut vm = Vm::new(program.clone()).unwrap();
+        vm.syscalls_mut().register(WRITE_STDOUT, |vm, ip| {
+            let v = super::arg(vm, ip)?;
+            super::ret(vm, ip, (v + Operand::Int(1)).unwrap())
+        });
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Int(21)));
+
+        let mut vm = Vm::new(program).unwrap();
+        assert!(vm.syscalls_mut().disable(WRITE_STDOUT));
+        assert!(!vm.syscalls_mut().disable(WRITE_STDOUT));
+        assert_eq!(
+            vm.run(),
+            Err(VmError::UnknownSyscall {
+                ip: 4,
+                id: WRITE_STDOUT
+            })
+        );
+
+        let mut vm = Vm::new(asm::assemble("push 3\nsyscall").unwrap()).unwrap();
+        *vm.syscalls_mut() = SyscallTable::new();
+        assert_eq!(vm.run(), Err(VmError::UnknownSyscall { ip: 2, id: EXIT }));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = Vm::new(asm::assemble("push \"x\"\npush 3\nsyscall").unwrap()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::InvalidOperand { ip: 4, .. })
+        ));
+
+        let mut vm = Vm::new(asm::assemble("push 0\nsyscall").unwrap()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::StackUnderflow { ip: 2, .. })
+        ));
+
+        let mut vm = Vm::new(asm::assemble("push \"0\"\nsyscall").unwrap()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::InvalidOperand { ip: 2, .. })
+        ));
+    }
+}
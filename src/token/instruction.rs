@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::token::Token;
 use instruction::Instruction;
 #[derive(Debug, PartialEq, Eq, Clone, Instruction)]
@@ -26,4 +28,78 @@ pub enum Instruction {
     Call,
 
     Write,
+
+    Arrnew,
+    Arrget,
+    Arrset,
+    Arrpush,
+    Arrpop,
+    Arrlen,
+
+    Mapnew,
+    Mapget,
+    Mapset,
+    Mapdel,
+    Maphas,
+    Mapkeys,
+
+    Tostr,
+    Tochar,
+    Tobytes,
+    Byteat,
+    Slice,
+
+    Syscall,
+    Callnative,
+
+    Readln,
+    Read,
+    Parseint,
+    Parsefloat,
+
+    Loadlocal,
+    Storelocal,
+}
+
+/// The mnemonic, as written in assembly.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Instruction {
+    /// Whether the instruction reads its argument from the data token that
+    /// follows it in the program.
+    pub fn takes_operand(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Push
+                | Instruction::Jmp
+                | Instruction::Jif
+                | Instruction::Load
+                | Instruction::Store
+                | Instruction::Call
+                | Instruction::Callnative
+                | Instruction::Loadlocal
+                | Instruction::Storelocal
+        )
+    }
+
+    /// Whether the operand is an address within the program.
+    pub fn takes_address(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Jmp | Instruction::Jif | Instruction::Call
+        )
+    }
+
+    /// One byte encoding used by the bytecode format.
+    pub fn opcode(&self) -> u8 {
+        self.clone() as u8
+    }
+
+    pub fn from_opcode(opcode: u8) -> Option<Instruction> {
+        Instruction::ALL.get(opcode as usize).cloned()
+    }
 }
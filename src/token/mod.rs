@@ -1,6 +1,9 @@
+use std::fmt;
+
 use self::instruction::Instruction;
 use self::operand::Operand;
 pub mod instruction;
+pub mod op;
 pub mod operand;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -15,6 +18,15 @@ impl Token {
     }
 }
 
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Instruction(i) => fmt::Display::fmt(i, f),
+            Token::Data(d) => fmt::Display::fmt(d, f),
+        }
+    }
+}
+
 impl TryInto<bool> for Token {
     type Error = String;
 
@@ -40,6 +52,7 @@ impl TryInto<Operand> for Token {
     }
 }
 
+#[cfg(test)]
 mod test {
     use super::{operand::Operand, Token};
 
@@ -54,6 +67,15 @@ mod test {
         let o: Result<Operand, _> = t.try_into();
         assert!(o.is_ok());
     }
+    #[test]
+    fn test_display() {
+        let t = Token::Instruction(super::instruction::Instruction::Callnative);
+        assert_eq!(t.to_string(), "callnative");
+        let t = Token::Data(Operand::Str(String::from("a")));
+        assert_eq!(t.to_string(), "a");
+        assert_eq!(format!("{:#}", t), "\"a\"");
+    }
+
     #[test]
     fn test_to_bool() {
         let t = Token::Instruction(super::instruction::Instruction::Add);
@@ -0,0 +1,352 @@
+//! Instructions with their operands, as the vm executes them.
+//!
+//! In the flat [`Token`] form an instruction reads its argument from the
+//! data token after it, so nothing stops a program from placing a `Str`
+//! where `jmp` expects an address or from ending right after `load`. An
+//! [`Op`] carries a typed argument instead. [`decode`] converts the flat
+//! form, checking every operand, and [`encode`] converts back. Both forms
+//! use the same addresses, jump targets are token addresses.
+//!
+//! ```
+//! use svm::token::op::{self, Op};
+//! use svm::token::operand::Operand;
+//!
+//! let program = svm::asm::assemble("push 1\njmp end\nend: halt").unwrap();
+//! let ops = op::decode(&program).unwrap();
+//! assert_eq!(ops, [Op::Push(Operand::Int(1)), Op::Jmp(4), Op::Halt]);
+//! assert_eq!(op::encode(&ops), program);
+//! ```
+
+use std::fmt;
+
+use crate::{
+    frame::MAX_LOCALS,
+    token::{instruction::Instruction, operand::Operand, Token},
+    VmError,
+};
+
+/// The function called by `callnative`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeRef {
+    Name(String),
+    Id(i64),
+}
+
+impl NativeRef {
+    /// The operand form, as looked up in [`crate::native::NativeRegistry`].
+    pub fn to_operand(&self) -> Operand {
+        match self {
+            NativeRef::Name(name) => Operand::Str(name.clone()),
+            NativeRef::Id(id) => Operand::Int(*id),
+        }
+    }
+}
+
+macro_rules! ops {
+    ($($plain:ident),* $(,)?) => {
+        /// An [`Instruction`] with its typed argument, if it takes one.
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum Op {
+            Push(Operand),
+            Jmp(usize),
+            Jif(usize),
+            Call(usize),
+            /// Reads the variable with this name.
+            Load(String),
+            Store(String),
+            Callnative(NativeRef),
+            /// Reads the local in this slot of the current frame.
+            Loadlocal(usize),
+            Storelocal(usize),
+            $($plain,)*
+        }
+
+        impl Op {
+            pub fn instruction(&self) -> Instruction {
+                match self {
+                    Op::Push(_) => Instruction::Push,
+                    Op::Jmp(_) => Instruction::Jmp,
+                    Op::Jif(_) => Instruction::Jif,
+                    Op::Call(_) => Instruction::Call,
+                    Op::Load(_) => Instruction::Load,
+                    Op::Store(_) => Instruction::Store,
+                    Op::Callnative(_) => Instruction::Callnative,
+                    Op::Loadlocal(_) => Instruction::Loadlocal,
+                    Op::Storelocal(_) => Instruction::Storelocal,
+                    $(Op::$plain => Instruction::$plain,)*
+                }
+            }
+
+            /// The op of an instruction without an argument.
+            fn plain(i: &Instruction) -> Option<Op> {
+                match i {
+                    $(Instruction::$plain => Some(Op::$plain),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+ops!(
+    Halt, Pop, Dup, Add, Sub, Mul, Div, Not, And, Or, Iseq, Isgt, Isge, Ret, Write, Arrnew, Arrget,
+    Arrset, Arrpush, Arrpop, Arrlen, Mapnew, Mapget, Mapset, Mapdel, Maphas, Mapkeys, Tostr,
+    Tochar, Tobytes, Byteat, Slice, Syscall, Readln, Read, Parseint, Parsefloat,
+);
+
+impl Op {
+    /// The argument as the data token of the flat form.
+    pub fn operand(&self) -> Option<Operand> {
+        match self {
+            Op::Push(v) => Some(v.clone()),
+            Op::Jmp(t) | Op::Jif(t) | Op::Call(t) => Some(Operand::Int(*t as i64)),
+            Op::Loadlocal(slot) | Op::Storelocal(slot) => Some(Operand::Int(*slot as i64)),
+            Op::Load(name) | Op::Store(name) => Some(Operand::Str(name.clone())),
+            Op::Callnative(native) => Some(native.to_operand()),
+            _ => None,
+        }
+    }
+
+    /// Number of tokens in the flat form.
+    pub fn size(&self) -> usize {
+        if self.instruction().takes_operand() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// The instruction as written in assembly, e.g. `load "a"`.
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.instruction())?;
+        match self.operand() {
+            Some(v) => write!(f, " {:#}", v),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Decodes the instruction at `address`, with the error the vm reports
+/// when executing a malformed one there.
+pub fn decode_at(program: &[Token], address: usize) -> Result<Op, VmError> {
+    let ip = address;
+    let i = match program.get(address) {
+        Some(Token::Instruction(i)) => i,
+        Some(Token::Data(_)) => return Err(VmError::DataExecuted { ip }),
+        None => return Err(VmError::IpOutOfBounds { ip }),
+    };
+    let unsupported = || VmError::UnsupportedInstruction {
+        ip,
+        instr: i.clone(),
+    };
+    if !i.takes_operand() {
+        return Op::plain(i).ok_or_else(unsupported);
+    }
+    let operand = match program.get(address + 1) {
+        Some(Token::Data(v)) => v.clone(),
+        _ => {
+            return Err(VmError::MissingOperand {
+                ip,
+                instr: i.clone(),
+            })
+        }
+    };
+    let target = |target| match target {
+        Operand::Int(t) if t >= 0 && (t as usize) < program.len() => Ok(t as usize),
+        target => Err(VmError::InvalidJumpTarget { ip, target }),
+    };
+    let invalid = |operand| VmError::InvalidOperand {
+        ip,
+        instr: i.clone(),
+        operand,
+    };
+    let name = |operand| match operand {
+        Operand::Str(name) => Ok(name),
+        operand => Err(invalid(operand)),
+    };
+    let slot = |operand| match operand {
+        Operand::Int(slot) if (0..MAX_LOCALS as i64).contains(&slot) => Ok(slot as usize),
+        operand => Err(invalid(operand)),
+    };
+    Ok(match i {
+        Instruction::Push => Op::Push(operand),
+        Instruction::Jmp => Op::Jmp(target(operand)?),
+        Instruction::Jif => Op::Jif(target(operand)?),
+        Instruction::Call => Op::Call(target(operand)?),
+        Instruction::Load => Op::Load(name(operand)?),
+        Instruction::Store => Op::Store(name(operand)?),
+        Instruction::Loadlocal => Op::Loadlocal(slot(operand)?),
+        Instruction::Storelocal => Op::Storelocal(slot(operand)?),
+        Instruction::Callnative => Op::Callnative(match operand {
+            Operand::Str(name) => NativeRef::Name(name),
+            Operand::Int(id) => NativeRef::Id(id),
+            native => return Err(VmError::UnknownNative { ip, native }),
+        }),
+        _ => return Err(unsupported()),
+    })
+}
+
+/// Decodes a whole program, failing at the first malformed instruction,
+/// data token that is not an operand or jump into an operand.
+pub fn decode(program: &[Token]) -> Result<Vec<Op>, VmError> {
+    let mut ops = Vec::new();
+    let mut addresses = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let op = decode_at(program, address)?;
+        addresses.push(address);
+        address += op.size();
+        ops.push(op);
+    }
+    for (op, &ip) in ops.iter().zip(&addresses) {
+        if let Op::Jmp(t) | Op::Jif(t) | Op::Call(t) = op {
+            if addresses.binary_search(t).is_err() {
+                let target = Operand::Int(*t as i64);
+                return Err(VmError::InvalidJumpTarget { ip, target });
+            }
+        }
+    }
+    Ok(ops)
+}
+
+/// The flat form of `ops`.
+pub fn encode(ops: &[Op]) -> Vec<Token> {
+    let mut program = Vec::with_capacity(ops.len());
+    for op in ops {
+        program.push(Token::Instruction(op.instruction()));
+        program.extend(op.operand().map(Token::Data));
+    }
+    program
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, decode_at, encode, NativeRef, Op};
+    use crate::{
+        asm::assemble,
+        tint,
+        token::{
+            instruction::{Instruction, *},
+            operand::Operand,
+            Token,
+        },
+        tstr, VmError,
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let program = assemble(
+            r#"
+                push 6
+                call max
+                store "x"
+                load "x"
+                storelocal 1
+                loadlocal 0
+                callnative "len"
+                callnative 2
+                halt
+            max:
+                jif max
+                ret"#,
+        )
+        .unwrap();
+        let ops = decode(&program).unwrap();
+        assert_eq!(
+            ops,
+            [
+                Op::Push(Operand::Int(6)),
+                Op::Call(17),
+                Op::Store(String::from("x")),
+                Op::Load(String::from("x")),
+                Op::Storelocal(1),
+                Op::Loadlocal(0),
+                Op::Callnative(NativeRef::Name(String::from("len"))),
+                Op::Callnative(NativeRef::Id(2)),
+                Op::Halt,
+                Op::Jif(17),
+                Op::Ret,
+            ]
+        );
+        assert_eq!(encode(&ops), program);
+        // Every instruction has an op.
+        for i in Instruction::ALL {
+            let mut program = vec![Token::Instruction(i.clone())];
+            if i.takes_operand() {
+                program.push(Token::Data(match i {
+                    Instruction::Load | Instruction::Store | Instruction::Callnative => {
+                        Operand::Str(String::from("x"))
+                    }
+                    _ => Operand::Int(0),
+                }));
+            }
+            assert_eq!(
+                decode(&program).map(|ops| ops[0].instruction()),
+                Ok(i.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            decode(&[STORE, tint!(0), HALT]),
+            Err(VmError::InvalidOperand {
+                ip: 0,
+                instr: Instruction::Store,
+                operand: Operand::Int(0)
+            })
+        );
+        assert_eq!(
+            decode(&[LOADLOCAL, tint!(-1)]),
+            Err(VmError::InvalidOperand {
+                ip: 0,
+                instr: Instruction::Loadlocal,
+                operand: Operand::Int(-1)
+            })
+        );
+        assert_eq!(
+            decode(&[STORELOCAL, tint!(4000000000000000000)]),
+            Err(VmError::InvalidOperand {
+                ip: 0,
+                instr: Instruction::Storelocal,
+                operand: Operand::Int(4000000000000000000)
+            })
+        );
+        assert_eq!(
+            decode(&[HALT, LOAD]),
+            Err(VmError::MissingOperand {
+                ip: 1,
+                instr: Instruction::Load
+            })
+        );
+        assert_eq!(
+            decode(&[JMP, tstr!(String::from("a"))]),
+            Err(VmError::InvalidJumpTarget {
+                ip: 0,
+                target: Operand::Str(String::from("a"))
+            })
+        );
+        assert_eq!(
+            decode(&[JMP, tint!(1)]),
+            Err(VmError::InvalidJumpTarget {
+                ip: 0,
+                target: Operand::Int(1)
+            })
+        );
+        assert_eq!(
+            decode(&[HALT, tint!(1)]),
+            Err(VmError::DataExecuted { ip: 1 })
+        );
+        assert_eq!(decode_at(&[HALT], 1), Err(VmError::IpOutOfBounds { ip: 1 }));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Op::Load(String::from("a")).to_string(), "load \"a\"");
+        assert_eq!(Op::Jmp(7).to_string(), "jmp 7");
+        assert_eq!(Op::Add.to_string(), "add");
+    }
+}
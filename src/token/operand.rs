@@ -1,31 +1,147 @@
-use std::ops::{Add, AddAssign, BitAnd, BitOr, Div, Mul, Sub};
-
-use super::Token;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::{Add, AddAssign, BitAnd, BitOr, Div, Mul, Sub},
+};
+
+/// Deepest nesting of arrays and maps accepted when reading programs, so
+/// that hostile input cannot overflow the stack.
+pub const MAX_NESTING: usize = 128;
 
 #[derive(Debug, Clone)]
-
 pub enum Operand {
     Null,
     Int(i64),
     Float(f64),
     Str(String),
     Bool(bool),
+    Char(char),
+    Bytes(Vec<u8>),
+    Array(Vec<Operand>),
+    /// Keys are limited to the types accepted by [`Operand::is_hashable`].
+    Map(HashMap<Operand, Operand>),
+}
+
+impl Operand {
+    /// Whether the value can be used as a map key.
+    pub fn is_hashable(&self) -> bool {
+        matches!(
+            self,
+            Operand::Int(_)
+                | Operand::Str(_)
+                | Operand::Bool(_)
+                | Operand::Char(_)
+                | Operand::Bytes(_)
+        )
+    }
+
+    /// The entries of `map` ordered by key, booleans before integers before
+    /// chars before strings before bytes, so that maps print and encode the same way every time.
+    pub fn sorted_entries(map: &HashMap<Operand, Operand>) -> Vec<(&Operand, &Operand)> {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by(|(a, _), (b, _)| Operand::key_order(a, b));
+        entries
+    }
+
+    fn key_order(a: &Operand, b: &Operand) -> Ordering {
+        let rank = |v: &Operand| match v {
+            Operand::Null => 0,
+            Operand::Bool(_) => 1,
+            Operand::Int(_) => 2,
+            Operand::Float(_) => 3,
+            Operand::Char(_) => 4,
+            Operand::Str(_) => 5,
+            Operand::Bytes(_) => 6,
+            Operand::Array(_) => 7,
+            Operand::Map(_) => 8,
+        };
+        match (a, b) {
+            (Operand::Bool(l), Operand::Bool(r)) => l.cmp(r),
+            (Operand::Int(l), Operand::Int(r)) => l.cmp(r),
+            (Operand::Float(l), Operand::Float(r)) => l.total_cmp(r),
+            (Operand::Char(l), Operand::Char(r)) => l.cmp(r),
+            (Operand::Str(l), Operand::Str(r)) => l.cmp(r),
+            (Operand::Bytes(l), Operand::Bytes(r)) => l.cmp(r),
+            _ => rank(a).cmp(&rank(b)),
+        }
+    }
+}
+
+/// Formats values the way programs print them: strings and chars as they
+/// are, floats always with a fraction or exponent and containers with their
+/// elements. The alternate form `{:#}` quotes strings and chars, as is done for
+/// the elements of arrays and maps.
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Null => write!(f, "null"),
+            Operand::Int(v) => write!(f, "{}", v),
+            Operand::Float(v) => write!(f, "{:?}", v),
+            Operand::Bool(v) => write!(f, "{}", v),
+            Operand::Str(v) if f.alternate() => write!(f, "{:?}", v),
+            Operand::Str(v) => write!(f, "{}", v),
+            Operand::Char(v) if f.alternate() => write!(f, "{:?}", v),
+            Operand::Char(v) => write!(f, "{}", v),
+            Operand::Bytes(v) => {
+                write!(f, "b\"")?;
+                for b in v {
+                    match b {
+                        b'"' | b'\\' => write!(f, "\\{}", *b as char)?,
+                        0x20..=0x7e => write!(f, "{}", *b as char)?,
+                        _ => write!(f, "\\x{:02x}", b)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Operand::Array(v) => {
+                write!(f, "[")?;
+                for (n, element) in v.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:#}", element)?;
+                }
+                write!(f, "]")
+            }
+            Operand::Map(v) => {
+                write!(f, "{{")?;
+                for (n, (key, value)) in Operand::sorted_entries(v).into_iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:#}: {:#}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Bits of `v` with every NaN and both zeros collapsed, so that equal floats
+/// hash the same.
+fn canonical_bits(v: f64) -> u64 {
+    if v.is_nan() {
+        f64::NAN.to_bits()
+    } else if v == 0.0 {
+        0
+    } else {
+        v.to_bits()
+    }
 }
 
 impl PartialEq<usize> for Operand {
     fn eq(&self, other: &usize) -> bool {
         match self {
             Operand::Int(v) => *v as usize == *other,
-            _ => panic!("Invalid operation, please check type"),
+            _ => false,
         }
     }
 }
 impl PartialEq<Operand> for usize {
     fn eq(&self, other: &Operand) -> bool {
-        match other {
-            Operand::Int(v) => *v as usize == *self,
-            _ => panic!("Invalid operation, please check type"),
-        }
+        other == self
     }
 }
 
@@ -39,13 +155,21 @@ impl PartialOrd<usize> for Operand {
     }
 }
 
+/// Orders values of the same type. Values that are equal compare as
+/// `Equal`, so NaN is equal to itself and booleans, arrays and maps, which
+/// have no order, compare only when equal.
 impl PartialOrd for Operand {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if self == other {
+            return Some(std::cmp::Ordering::Equal);
+        }
         match (self, other) {
             (Self::Int(l0), Self::Int(r0)) => l0.partial_cmp(r0),
             (Self::Float(l0), Self::Float(r0)) => l0.partial_cmp(r0),
             (Self::Str(l0), Self::Str(r0)) => l0.partial_cmp(r0),
-            _ => panic!("Invalid comparison, please check type"),
+            (Self::Char(l0), Self::Char(r0)) => l0.partial_cmp(r0),
+            (Self::Bytes(l0), Self::Bytes(r0)) => l0.partial_cmp(r0),
+            _ => None,
         }
     }
 }
@@ -80,110 +204,143 @@ impl TryInto<usize> for Operand {
     }
 }
 
+/// Values of different types are never equal. Floats compare by value except
+/// that NaN equals itself, which keeps `Eq` and `Hash` consistent.
 impl PartialEq for Operand {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Self::Null, Self::Null) => true,
             (Self::Int(l0), Self::Int(r0)) => l0 == r0,
-            (Self::Float(l0), Self::Float(r0)) => l0 == r0,
+            (Self::Float(l0), Self::Float(r0)) => canonical_bits(*l0) == canonical_bits(*r0),
             (Self::Str(l0), Self::Str(r0)) => l0 == r0,
             (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
-            _ => core::mem::discriminant(self) == core::mem::discriminant(other),
+            (Self::Char(l0), Self::Char(r0)) => l0 == r0,
+            (Self::Bytes(l0), Self::Bytes(r0)) => l0 == r0,
+            (Self::Array(l0), Self::Array(r0)) => l0 == r0,
+            (Self::Map(l0), Self::Map(r0)) => l0 == r0,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Operand {}
+
+impl Hash for Operand {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            Operand::Null => {}
+            Operand::Int(v) => v.hash(state),
+            Operand::Float(v) => canonical_bits(*v).hash(state),
+            Operand::Str(v) => v.hash(state),
+            Operand::Bool(v) => v.hash(state),
+            Operand::Char(v) => v.hash(state),
+            Operand::Bytes(v) => v.hash(state),
+            Operand::Array(v) => v.hash(state),
+            // Entries have no order, the length is all that is cheap to hash
+            // consistently with `Eq`.
+            Operand::Map(v) => v.len().hash(state),
         }
     }
 }
 
 impl Add for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Int(l), Operand::Int(r)) => Operand::Int(l + r),
-            (Operand::Float(l), Operand::Float(r)) => Operand::Float(l + r),
-            (Operand::Float(l), Operand::Int(r)) => Operand::Float(l + r as f64),
-            (Operand::Int(l), Operand::Float(r)) => Operand::Float(l as f64 + r),
-            (Operand::Str(l), Operand::Str(r)) => {
-                let mut l = l.clone();
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Int(l), Operand::Int(r)) => l.checked_add(r).map(Operand::Int),
+            (Operand::Float(l), Operand::Float(r)) => Some(Operand::Float(l + r)),
+            (Operand::Float(l), Operand::Int(r)) => Some(Operand::Float(l + r as f64)),
+            (Operand::Int(l), Operand::Float(r)) => Some(Operand::Float(l as f64 + r)),
+            (Operand::Str(mut l), Operand::Str(r)) => {
                 l.push_str(&r);
-                Operand::Str(l)
+                Some(Operand::Str(l))
             }
-            _ => {
-                panic!("Invalid operation, please check type")
+            (Operand::Str(mut l), Operand::Char(r)) => {
+                l.push(r);
+                Some(Operand::Str(l))
+            }
+            (Operand::Char(l), Operand::Str(r)) => Some(Operand::Str(format!("{}{}", l, r))),
+            (Operand::Char(l), Operand::Char(r)) => Some(Operand::Str(format!("{}{}", l, r))),
+            (Operand::Bytes(mut l), Operand::Bytes(r)) => {
+                l.extend_from_slice(&r);
+                Some(Operand::Bytes(l))
             }
+            _ => None,
         }
     }
 }
 
 impl Mul for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Int(l), Operand::Int(r)) => Operand::Int(l * r),
-            (Operand::Float(l), Operand::Float(r)) => Operand::Float(l * r),
-            (Operand::Float(l), Operand::Int(r)) => Operand::Float(l * r as f64),
-            (Operand::Int(l), Operand::Float(r)) => Operand::Float(l as f64 * r),
-
-            // (Operand::Int(l), Operand::Float(r)) => Operand::Float(l as f64 + r),
-            _ => {
-                panic!("Invalid operation, please check type")
-            }
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Int(l), Operand::Int(r)) => l.checked_mul(r).map(Operand::Int),
+            (Operand::Float(l), Operand::Float(r)) => Some(Operand::Float(l * r)),
+            (Operand::Float(l), Operand::Int(r)) => Some(Operand::Float(l * r as f64)),
+            (Operand::Int(l), Operand::Float(r)) => Some(Operand::Float(l as f64 * r)),
+            _ => None,
         }
     }
 }
 
 impl Div for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
+    /// Integer division by zero or overflow yields `None` as well, callers
+    /// that need to tell them apart from a type mismatch should check the
+    /// operands first.
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Int(l), Operand::Int(r)) => Operand::Int(l / r),
-            (Operand::Float(l), Operand::Float(r)) => Operand::Float(l / r),
-            (Operand::Int(l), Operand::Float(r)) => Operand::Float(l as f64 / r),
-            (Operand::Float(l), Operand::Int(r)) => Operand::Float(l / r as f64),
-            _ => panic!("Invalid operation, please check type"),
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Int(l), Operand::Int(r)) => l.checked_div(r).map(Operand::Int),
+            (Operand::Float(l), Operand::Float(r)) => Some(Operand::Float(l / r)),
+            (Operand::Int(l), Operand::Float(r)) => Some(Operand::Float(l as f64 / r)),
+            (Operand::Float(l), Operand::Int(r)) => Some(Operand::Float(l / r as f64)),
+            _ => None,
         }
     }
 }
 
 impl Sub for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Int(l), Operand::Int(r)) => Operand::Int(l - r),
-            (Operand::Float(l), Operand::Float(r)) => Operand::Float(l - r),
-            (Operand::Int(l), Operand::Float(r)) => Operand::Float(l as f64 - r),
-            (Operand::Float(l), Operand::Int(r)) => Operand::Float(l - r as f64),
-
-            _ => panic!("Invalid operation, please check type"),
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Int(l), Operand::Int(r)) => l.checked_sub(r).map(Operand::Int),
+            (Operand::Float(l), Operand::Float(r)) => Some(Operand::Float(l - r)),
+            (Operand::Int(l), Operand::Float(r)) => Some(Operand::Float(l as f64 - r)),
+            (Operand::Float(l), Operand::Int(r)) => Some(Operand::Float(l - r as f64)),
+            _ => None,
         }
     }
 }
 
 impl BitAnd for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
     fn bitand(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Bool(l), Operand::Bool(r)) => Operand::Bool(l & r),
-            _ => panic!("Invalid operation, please check type"),
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Bool(l), Operand::Bool(r)) => Some(Operand::Bool(l & r)),
+            _ => None,
         }
     }
 }
 
 impl BitOr for Operand {
-    type Output = Operand;
+    type Output = Option<Operand>;
 
     fn bitor(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Operand::Null, Operand::Null) => Operand::Null,
-            (Operand::Bool(l), Operand::Bool(r)) => Operand::Bool(l | r),
-            _ => panic!("Invalid operation, please check type"),
+            (Operand::Null, Operand::Null) => Some(Operand::Null),
+            (Operand::Bool(l), Operand::Bool(r)) => Some(Operand::Bool(l | r)),
+            _ => None,
         }
     }
 }
@@ -251,10 +408,8 @@ impl Add<String> for Operand {
 impl Add<bool> for Operand {
     type Output = Operand;
 
-    fn add(self, rhs: bool) -> Self::Output {
-        match self {
-            _ => panic!("Invalid operation, please check type"),
-        }
+    fn add(self, _rhs: bool) -> Self::Output {
+        panic!("Invalid operation, please check type")
     }
 }
 
@@ -271,6 +426,7 @@ impl AddAssign for Operand {
         }
     }
 }
+#[cfg(test)]
 mod test {
     use crate::{bool, float, int, str, token::Operand};
 
@@ -295,5 +451,130 @@ mod test {
         let mut a = bool!(true);
         a += bool!(false);
     }
+    #[test]
+    fn test_mismatched_ops() {
+        assert_eq!(int!(1) + str!(String::from("a")), None);
+        assert_eq!(bool!(true) - bool!(false), None);
+        assert_eq!(int!(1) / int!(0), None);
+        assert_eq!(Operand::Int(i64::MAX) + int!(1), None);
+        assert_eq!(Operand::Int(i64::MIN) / int!(-1), None);
+        assert_eq!(int!(1).partial_cmp(&bool!(true)), None);
+        assert_eq!(bool!(true).partial_cmp(&bool!(false)), None);
+        assert_eq!(int!(6) / float!(4.0), Some(float!(1.5)));
+    }
+
+    #[test]
+    fn test_array_eq() {
+        let a = Operand::Array(vec![int!(1), str!(String::from("a"))]);
+        assert_eq!(a, a.clone());
+        assert_ne!(a, Operand::Array(vec![int!(1)]));
+        assert_ne!(a, Operand::Array(vec![int!(1), str!(String::from("b"))]));
+        assert_eq!(a.clone() + a, None);
+    }
+
+    #[test]
+    fn test_eq_and_hash() {
+        use std::collections::HashSet;
+
+        assert_eq!(Operand::Null, Operand::Null);
+        assert_ne!(int!(1), float!(1.0));
+        assert_ne!(int!(0), bool!(false));
+        assert_eq!(float!(0.0), float!(-0.0));
+        assert_eq!(Operand::Float(f64::NAN), Operand::Float(f64::NAN));
+
+        let set: HashSet<Operand> = [int!(1), int!(1), float!(0.0), float!(-0.0), bool!(true)]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 3);
+
+        let map = |entries: Vec<(Operand, Operand)>| Operand::Map(entries.into_iter().collect());
+        let a = map(vec![
+            (int!(1), bool!(true)),
+            (str!(String::from("b")), int!(2)),
+        ]);
+        let b = map(vec![
+            (str!(String::from("b")), int!(2)),
+            (int!(1), bool!(true)),
+        ]);
+        assert_eq!(a, b);
+        assert_ne!(a, map(vec![(int!(1), bool!(true))]));
+
+        // Ordering agrees with equality.
+        use std::cmp::Ordering::Equal;
+        let nan = Operand::Float(f64::NAN);
+        assert_eq!(nan.partial_cmp(&nan), Some(Equal));
+        assert_eq!(nan.partial_cmp(&float!(1.0)), None);
+        assert_eq!(float!(0.0).partial_cmp(&float!(-0.0)), Some(Equal));
+        assert_eq!(Operand::Null.partial_cmp(&Operand::Null), Some(Equal));
+        assert_eq!(a.partial_cmp(&b), Some(Equal));
+    }
+
+    #[test]
+    fn test_sorted_entries() {
+        let map = [
+            (str!(String::from("a")), int!(1)),
+            (int!(2), int!(2)),
+            (bool!(true), int!(3)),
+            (int!(-1), int!(4)),
+        ]
+        .into_iter()
+        .collect();
+        let keys: Vec<&Operand> = Operand::sorted_entries(&map)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(
+            keys,
+            [&bool!(true), &int!(-1), &int!(2), &str!(String::from("a"))]
+        );
+    }
+
+    #[test]
+    fn test_chars_and_bytes() {
+        let s = |v: &str| str!(String::from(v));
+        assert_eq!(s("ab") + Operand::Char('c'), Some(s("abc")));
+        assert_eq!(Operand::Char('a') + s("bc"), Some(s("abc")));
+        assert_eq!(Operand::Char('a') + Operand::Char('b'), Some(s("ab")));
+        assert_eq!(
+            Operand::Bytes(vec![1]) + Operand::Bytes(vec![2, 3]),
+            Some(Operand::Bytes(vec![1, 2, 3]))
+        );
+        assert_eq!(Operand::Bytes(vec![1]) + s("a"), None);
+        assert_ne!(Operand::Char('a'), s("a"));
+        assert!(Operand::Char('a') < Operand::Char('b'));
+        assert!(Operand::Bytes(vec![1, 2]) < Operand::Bytes(vec![1, 3]));
+        assert!(Operand::Bytes(vec![1]) < Operand::Bytes(vec![1, 0]));
+        assert_eq!(Operand::Char('a').partial_cmp(&s("a")), None);
+        assert!(Operand::Char('a').is_hashable());
+    }
+
+    #[test]
+    fn test_display() {
+        let s = |v: &str| str!(String::from(v));
+        assert_eq!(Operand::Null.to_string(), "null");
+        assert_eq!(int!(-3).to_string(), "-3");
+        assert_eq!(float!(2.0).to_string(), "2.0");
+        assert_eq!(float!(0.1).to_string(), "0.1");
+        assert_eq!(Operand::Float(f64::INFINITY).to_string(), "inf");
+        assert_eq!(bool!(true).to_string(), "true");
+        assert_eq!(s("a \"b\"").to_string(), "a \"b\"");
+        assert_eq!(format!("{:#}", s("a\n")), "\"a\\n\"");
+        assert_eq!(Operand::Char('x').to_string(), "x");
+        assert_eq!(format!("{:#}", Operand::Char('x')), "'x'");
+        assert_eq!(
+            Operand::Bytes(vec![b'a', b'"', 0]).to_string(),
+            "b\"a\\\"\\x00\""
+        );
+        assert_eq!(
+            Operand::Array(vec![int!(1), s("two"), Operand::Array(vec![])]).to_string(),
+            "[1, \"two\", []]"
+        );
+        let map: Operand = Operand::Map(
+            [(s("b"), Operand::Char('c')), (int!(1), Operand::Null)]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(map.to_string(), "{1: null, \"b\": 'c'}");
+    }
     //TODO: Add more tests
 }
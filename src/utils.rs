@@ -1,7 +1,7 @@
 #[macro_export]
 macro_rules! data {
     ($x:expr) => {
-        crate::token::Token::data($x)
+        $crate::token::Token::data($x)
     };
 }
 
@@ -15,7 +15,7 @@ macro_rules! int {
 #[macro_export]
 macro_rules! tint {
     ($x:literal) => {
-        crate::token::Token::data(crate::token::operand::Operand::Int($x))
+        $crate::token::Token::data($crate::token::operand::Operand::Int($x))
     };
 }
 
@@ -29,7 +29,7 @@ macro_rules! float {
 #[macro_export]
 macro_rules! tfloat {
     ($x:literal) => {
-        crate::token::Token::data(crate::token::operand::Operand::Float($x))
+        $crate::token::Token::data($crate::token::operand::Operand::Float($x))
     };
 }
 
@@ -43,7 +43,7 @@ macro_rules! str {
 #[macro_export]
 macro_rules! tstr {
     ($x:expr) => {
-        crate::token::Token::data(crate::token::operand::Operand::Str($x))
+        $crate::token::Token::data($crate::token::operand::Operand::Str($x))
     };
 }
 
@@ -57,7 +57,7 @@ macro_rules! bool {
 #[macro_export]
 macro_rules! tbool {
     ($x:literal) => {
-        crate::token::Token::data(crate::token::operand::Operand::Bool($x))
+        $crate::token::Token::data($crate::token::operand::Operand::Bool($x))
     };
 }
 
@@ -0,0 +1,428 @@
+//! Static checks that catch malformed programs before they run.
+//!
+//! [`verify`] follows every path from address 0 and reports
+//!
+//! - operand-taking instructions without a data token of the right type,
+//! - `jmp`, `jif` and `call` targets that are not instructions,
+//! - data tokens and the end of the program reachable as code,
+//! - instructions that pop more values than the stack holds on every path
+//!   reaching them.
+//!
+//! A stack depth is only known where every path reaching an instruction
+//! agrees on it, otherwise it is not checked. After `call`, `syscall`,
+//! `callnative` and `arrnew` the depth depends on the callee or on runtime
+//! values and is unknown as well. Paths are not checked for feasibility, a
+//! conditional jump is assumed to go both ways.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    frame::MAX_LOCALS,
+    token::{instruction::Instruction, operand::Operand, Token},
+};
+
+/// Returns every problem found, ordered by address.
+pub fn verify(program: &[Token]) -> Result<(), Vec<VerifyError>> {
+    let mut verifier = Verifier {
+        program,
+        depths: vec![None; program.len()],
+        errors: BTreeMap::new(),
+    };
+    verifier.run();
+    if verifier.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(verifier.errors.into_values().collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub address: usize,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    /// The instruction takes an operand but is not followed by data.
+    MissingOperand(Instruction),
+    /// The operand has the wrong type for the instruction.
+    InvalidOperand {
+        instr: Instruction,
+        operand: Operand,
+    },
+    /// The target is outside the program or not an instruction.
+    InvalidTarget { instr: Instruction, target: Operand },
+    /// Execution can reach a data token.
+    DataReachable,
+    /// Execution can run past the last token.
+    RunsOffEnd,
+    /// On every path the stack holds only `depth` values here.
+    StackUnderflow { instr: Instruction, depth: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}: ", self.address)?;
+        match &self.kind {
+            VerifyErrorKind::MissingOperand(i) => write!(f, "{} expects an operand", i),
+            VerifyErrorKind::InvalidOperand { instr, operand } => {
+                write!(f, "invalid operand {:#} for {}", operand, instr)
+            }
+            VerifyErrorKind::InvalidTarget { instr, target } => {
+                write!(f, "{} target {:#} is not an instruction", instr, target)
+            }
+            VerifyErrorKind::DataReachable => write!(f, "data is reachable as code"),
+            VerifyErrorKind::RunsOffEnd => write!(f, "execution runs past the end"),
+            VerifyErrorKind::StackUnderflow { instr, depth } => write!(
+                f,
+                "stack underflow, {} runs with {} values on the stack",
+                instr, depth
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// How an instruction changes the stack.
+enum Effect {
+    Fixed {
+        pops: usize,
+        pushes: usize,
+    },
+    /// Pops at least `pops` values and leaves an unknown depth.
+    Variable {
+        pops: usize,
+    },
+}
+
+fn effect(i: &Instruction) -> Effect {
+    use Instruction::*;
+    let (pops, pushes) = match i {
+        Arrnew | Syscall => return Effect::Variable { pops: 1 },
+        Call | Callnative => return Effect::Variable { pops: 0 },
+        Halt | Jmp | Ret | Write => (0, 0),
+        Push | Load | Loadlocal | Mapnew | Readln | Read => (0, 1),
+        Pop | Jif | Store | Storelocal => (1, 0),
+        Dup | Arrpop => (1, 2),
+        Not | Arrlen | Mapkeys | Tostr | Tochar | Tobytes | Parseint | Parsefloat => (1, 1),
+        Add | Sub | Mul | Div | And | Or | Iseq | Isgt | Isge => (2, 1),
+        Arrget | Arrpush | Mapget | Mapdel | Maphas | Byteat => (2, 1),
+        Arrset | Mapset | Slice => (3, 1),
+    };
+    Effect::Fixed { pops, pushes }
+}
+
+struct Verifier<'a> {
+    program: &'a [Token],
+    /// Stack depth of every path seen at each visited address, `Some(None)`
+    /// once the depth is unknown or the paths disagree.
+    depths: Vec<Option<Option<usize>>>,
+    errors: BTreeMap<usize, VerifyError>,
+}
+
+impl Verifier<'_> {
+    fn run(&mut self) {
+        let mut pending = vec![(0, Some(0))];
+        while let Some((address, depth)) = pending.pop() {
+            let Some(depth) = self.merge(address, depth) else {
+                continue;
+            };
+            if let Some(next) = self.visit(address, depth) {
+                pending.extend(next);
+            }
+        }
+    }
+
+    /// Records `depth` at `address` and returns the depth to continue with,
+    /// or `None` if nothing changed since the last visit.
+    fn merge(&mut self, address: usize, depth: Option<usize>) -> Option<Option<usize>> {
+        let Some(slot) = self.depths.get_mut(address) else {
+            // Only reported once, there is no slot to remember it.
+            self.error(address, VerifyErrorKind::RunsOffEnd);
+            return None;
+        };
+        let merged = match (*slot, depth) {
+            (None, depth) => depth,
+            (Some(old), new) if old == new => return None,
+            (Some(Some(_)), _) => None,
+            (Some(None), _) => return None,
+        };
+        *slot = Some(merged);
+        if merged.is_none() {
+            // Found with a depth that turned out to be path dependent.
+            if let Some(VerifyError {
+                kind: VerifyErrorKind::StackUnderflow { .. },
+                ..
+            }) = self.errors.get(&address)
+            {
+                self.errors.remove(&address);
+            }
+        }
+        Some(merged)
+    }
+
+    /// Checks the instruction at `address` and returns its successors.
+    fn visit(
+        &mut self,
+        address: usize,
+        depth: Option<usize>,
+    ) -> Option<Vec<(usize, Option<usize>)>> {
+        let i = match &self.program[address] {
+            Token::Instruction(i) => i.clone(),
+            Token::Data(_) => {
+                self.error(address, VerifyErrorKind::DataReachable);
+                return None;
+            }
+        };
+        let mut next = address + 1;
+        let mut target = None;
+        if i.takes_operand() {
+            let operand = match self.program.get(address + 1) {
+                Some(Token::Data(v)) => v,
+                _ => {
+                    self.error(address, VerifyErrorKind::MissingOperand(i));
+                    return None;
+                }
+            };
+            next += 1;
+            let valid = match &i {
+                Instruction::Load | Instruction::Store => matches!(operand, Operand::Str(_)),
+                Instruction::Callnative => matches!(operand, Operand::Str(_) | Operand::Int(_)),
+                Instruction::Loadlocal | Instruction::Storelocal => {
+                    matches!(operand, Operand::Int(slot) if (0..MAX_LOCALS as i64).contains(slot))
+                }
+                i if i.takes_address() => matches!(operand, Operand::Int(_)),
+                _ => true,
+            };
+            if !valid {
+                let operand = operand.clone();
+                self.error(
+                    address,
+                    VerifyErrorKind::InvalidOperand { instr: i, operand },
+                );
+                return None;
+            }
+            if i.takes_address() {
+                match operand {
+                    Operand::Int(t) if self.is_instruction(*t) => target = Some(*t as usize),
+                    _ => {
+                        let target = operand.clone();
+                        self.error(address, VerifyErrorKind::InvalidTarget { instr: i, target });
+                        return None;
+                    }
+                }
+            }
+        }
+
+        let (pops, after) = match effect(&i) {
+            Effect::Fixed { pops, pushes } => {
+                (pops, depth.map(|d| d.saturating_sub(pops) + pushes))
+            }
+            Effect::Variable { pops } => (pops, None),
+        };
+        if let Some(depth) = depth.filter(|depth| *depth < pops) {
+            self.error(address, VerifyErrorKind::StackUnderflow { instr: i, depth });
+            return None;
+        }
+        // Callees see the caller's stack.
+        let callee = depth;
+        Some(match i {
+            Instruction::Halt | Instruction::Ret => vec![],
+            Instruction::Jmp => vec![(target.unwrap(), after)],
+            Instruction::Jif => vec![(next, after), (target.unwrap(), after)],
+            Instruction::Call => vec![(next, after), (target.unwrap(), callee)],
+            _ => vec![(next, after)],
+        })
+    }
+
+    fn is_instruction(&self, address: i64) -> bool {
+        usize::try_from(address)
+            .ok()
+            .and_then(|address| self.program.get(address))
+            .is_some_and(|token| matches!(token, Token::Instruction(_)))
+    }
+
+    fn error(&mut self, address: usize, kind: VerifyErrorKind) {
+        self.errors.insert(address, VerifyError { address, kind });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify, VerifyError, VerifyErrorKind};
+    use crate::{
+        asm::assemble,
+        tint,
+        token::{
+            instruction::{Instruction, *},
+            operand::Operand,
+        },
+    };
+
+    fn errors(src: &str) -> Vec<VerifyError> {
+        verify(&assemble(src).unwrap()).unwrap_err()
+    }
+
+    fn error(address: usize, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError { address, kind }
+    }
+
+    #[test]
+    fn test_valid() {
+        let src = r#"
+                push 6
+                push 4
+                call max
+                halt
+            max:
+                store "b"
+                store "a"
+                load "a"
+                load "b"
+                isge
+                jif a
+                load "b"
+                ret
+            a:  load "a"
+                ret"#;
+        assert_eq!(verify(&assemble(src).unwrap()), Ok(()));
+
+        // Loops that grow the stack still terminate.
+        let src = "loop: push 1\npush true\njif loop\npop\nhalt";
+        assert_eq!(verify(&assemble(src).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!(
+            verify(&[STORE, tint!(0), HALT]),
+            Err(vec![error(
+                0,
+                VerifyErrorKind::InvalidOperand {
+                    instr: Instruction::Store,
+                    operand: Operand::Int(0)
+                }
+            )])
+        );
+        assert_eq!(
+            verify(&[STORELOCAL, tint!(65536), HALT]),
+            Err(vec![error(
+                0,
+                VerifyErrorKind::InvalidOperand {
+                    instr: Instruction::Storelocal,
+                    operand: Operand::Int(65536)
+                }
+            )])
+        );
+        assert_eq!(
+            verify(&[LOAD]),
+            Err(vec![error(
+                0,
+                VerifyErrorKind::MissingOperand(Instruction::Load)
+            )])
+        );
+        assert_eq!(
+            errors("jmp 1\nhalt"),
+            [error(
+                0,
+                VerifyErrorKind::InvalidTarget {
+                    instr: Instruction::Jmp,
+                    target: Operand::Int(1)
+                }
+            )]
+        );
+        assert_eq!(
+            errors("call 7\nhalt"),
+            [error(
+                0,
+                VerifyErrorKind::InvalidTarget {
+                    instr: Instruction::Call,
+                    target: Operand::Int(7)
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_reachability() {
+        assert_eq!(
+            errors("push 1\n.data 2"),
+            [error(2, VerifyErrorKind::DataReachable)]
+        );
+        assert_eq!(errors("push 1"), [error(2, VerifyErrorKind::RunsOffEnd)]);
+        // Unreachable data is fine.
+        assert_eq!(verify(&assemble("halt\n.data 2").unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        assert_eq!(
+            errors("push 1\nadd\nhalt"),
+            [error(
+                2,
+                VerifyErrorKind::StackUnderflow {
+                    instr: Instruction::Add,
+                    depth: 1
+                }
+            )]
+        );
+        // Every path reaching the second `pop` underflows.
+        assert_eq!(
+            errors("push 1\npush true\njif skip\nskip: pop\npop\nhalt"),
+            [error(
+                7,
+                VerifyErrorKind::StackUnderflow {
+                    instr: Instruction::Pop,
+                    depth: 0
+                }
+            )]
+        );
+        // Only one of the paths reaching `pop` underflows, which may never
+        // be taken.
+        assert_eq!(
+            verify(&assemble("push false\njif skip\npush 1\nskip: pop\nhalt").unwrap()),
+            Ok(())
+        );
+        let sum = r#"
+                push 0
+                store "sum"
+                push 10
+                store "i"
+            loop:
+                load "sum"
+                load "i"
+                add
+                store "sum"
+                load "i"
+                push -1
+                add
+                dup
+                store "i"
+                push 0
+                isgt
+                jif loop
+                load "sum"
+                halt"#;
+        assert_eq!(verify(&assemble(sum).unwrap()), Ok(()));
+        // The stack is unknown after a call.
+        assert_eq!(
+            verify(&assemble("call f\npop\nhalt\nf: push 1\nret").unwrap()),
+            Ok(())
+        );
+        // Callees see the arguments of the caller.
+        assert_eq!(
+            verify(&assemble("push 1\ncall f\nhalt\nf: pop\nret").unwrap()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            errors("push 1\nadd\nhalt")[0].to_string(),
+            "0002: stack underflow, add runs with 1 values on the stack"
+        );
+    }
+}
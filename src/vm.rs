@@ -1,155 +1,842 @@
 use crate::{
     data,
-    frame::Frame,
-    int, stack, tbool,
-    token::{instruction::Instruction, operand::Operand, *},
+    error::VmError,
+    frame::{Frame, LocalNames},
+    native::NativeRegistry,
+    stack,
+    syscall::SyscallTable,
+    token::{
+        instruction::Instruction,
+        op::{self, Op},
+        operand::Operand,
+        *,
+    },
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, Read, Write},
+    ops::Range,
+    rc::Rc,
 };
-use std::{collections::VecDeque, default};
 
-struct Vm {
+/// A stack based virtual machine executing a program of [`Token`]s.
+///
+/// The operand stack grows at the front, so the head of [`Vm::stack`] is the
+/// top of the stack.
+///
+/// ```
+/// use svm::token::{instruction::*, operand::Operand};
+/// use svm::Vm;
+///
+/// let mut vm = Vm::new(vec![ADD, HALT]).unwrap();
+/// vm.push(Operand::Int(2));
+/// vm.push(Operand::Int(3));
+/// vm.run().unwrap();
+/// assert!(vm.is_halted());
+/// assert_eq!(vm.pop(), Some(Operand::Int(5)));
+/// ```
+pub struct Vm {
     halted: bool,
     ip: usize, //Instruction Pointer
     stack: VecDeque<Token>,
     program: Vec<Token>,
+    /// The program decoded, shared so that executing one does not borrow
+    /// the vm.
+    ops: Rc<[Op]>,
+    /// Index in `ops` of the instruction at each address, `None` for
+    /// operands.
+    index: Vec<Option<usize>>,
     frames: VecDeque<Frame>,
+    /// Number of local slots of each function and their names.
+    local_names: LocalNames,
+    /// Slots in all frames together.
+    locals: usize,
+    /// Instructions left to execute, unlimited when `None`.
+    fuel: Option<u64>,
+    config: VmConfig,
+    stats: VmStats,
+    syscalls: SyscallTable,
+    natives: NativeRegistry,
+    /// Where `Write` and the stdout syscall go.
+    output: Box<dyn Write>,
+    /// Where `Readln`, `Read` and the read syscall come from, stdin if `None`.
+    input: Option<Box<dyn BufRead>>,
+    /// Set by the exit syscall.
+    exit_code: Option<i64>,
+}
+
+/// Resource limits of a [`Vm`], `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VmConfig {
+    /// Most values the operand stack may hold.
+    pub max_stack_size: Option<usize>,
+    /// Most calls that may be active at once.
+    pub max_call_depth: Option<usize>,
+    /// Most local slots all active frames may hold together.
+    pub max_locals: Option<usize>,
+    /// Initial instruction budget, see [`Vm::run_with_fuel`].
+    pub fuel: Option<u64>,
+}
+
+/// High-watermarks observed while running, useful to size [`VmConfig`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VmStats {
+    pub max_stack_size: usize,
+    pub max_call_depth: usize,
+    pub max_locals: usize,
 }
 
 impl Vm {
-    pub fn new(program: Vec<Token>) -> Self {
-        Self {
+    /// A vm ready to run `program`, failing if it cannot be decoded with
+    /// [`op::decode`]. Names loaded and stored by the program are given
+    /// slots with [`LocalNames::resolve`] first.
+    pub fn new(program: Vec<Token>) -> Result<Self, VmError> {
+        Vm::with_config(program, VmConfig::default())
+    }
+
+    pub fn with_config(mut program: Vec<Token>, config: VmConfig) -> Result<Self, VmError> {
+        let mut local_names = LocalNames::new();
+        local_names.resolve(&mut program);
+        let ops = op::decode(&program)?;
+        let mut vm = Self {
             halted: false,
             ip: 0,
             stack: VecDeque::new(),
+            index: Vm::index(&ops, program.len()),
+            ops: ops.into(),
             program,
-            frames: stack![Frame::default()],
+            frames: stack![Frame::new(0, 0, 0)],
+            local_names,
+            locals: 0,
+            fuel: config.fuel,
+            config,
+            stats: VmStats::default(),
+            syscalls: SyscallTable::standard(),
+            natives: NativeRegistry::new(),
+            output: Box::new(io::stdout()),
+            input: None,
+            exit_code: None,
+        };
+        vm.resize_frames(0)?;
+        Ok(vm)
+    }
+
+    /// Maps each address of a program of `len` tokens to its op in `ops`.
+    fn index(ops: &[Op], len: usize) -> Vec<Option<usize>> {
+        let mut index = vec![None; len];
+        let mut address = 0;
+        for (n, op) in ops.iter().enumerate() {
+            index[address] = Some(n);
+            address += op.size();
+        }
+        index
+    }
+
+    /// Sends the program's output to `output` instead of stdout, see
+    /// [`crate::output::Capture`] to collect it.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.set_output(output);
+        self
+    }
+
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// The destination of the program's output.
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    /// Names and sizes the local slots of the program's functions, e.g. as
+    /// returned by [`crate::lang::compile_with_locals`]. Frames of the
+    /// functions it leaves out get as many slots as the program uses, and
+    /// names the program already has keep their slots, see
+    /// [`LocalNames::merge`].
+    pub fn with_local_names(mut self, names: LocalNames) -> Self {
+        self.local_names.merge(names);
+        for frame in &mut self.frames {
+            frame.resize(self.local_names.size(frame.function()));
+        }
+        self.locals = self.frames.iter().map(|f| f.locals().len()).sum();
+        self.stats.max_locals = self.stats.max_locals.max(self.locals);
+        self
+    }
+
+    pub fn local_names(&self) -> &LocalNames {
+        &self.local_names
+    }
+
+    /// Reads the program's input from `input` instead of stdin.
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.set_input(input);
+        self
+    }
+
+    pub fn set_input(&mut self, input: impl BufRead + 'static) {
+        self.input = Some(Box::new(input));
+    }
+
+    /// Reads a line of input without its line ending, `None` at the end.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        let n = match &mut self.input {
+            Some(input) => input.read_line(&mut line)?,
+            None => io::stdin().lock().read_line(&mut line)?,
+        };
+        if n == 0 {
+            return Ok(None);
         }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(Some(line))
+    }
+
+    /// Reads the rest of the input, `None` if it has already ended.
+    pub fn read_all(&mut self) -> io::Result<Option<String>> {
+        let mut s = String::new();
+        let n = match &mut self.input {
+            Some(input) => input.read_to_string(&mut s)?,
+            None => io::stdin().lock().read_to_string(&mut s)?,
+        };
+        Ok(if n == 0 { None } else { Some(s) })
+    }
+
+    pub fn config(&self) -> &VmConfig {
+        &self.config
     }
-    pub fn run(&mut self) {
+
+    pub fn stats(&self) -> &VmStats {
+        &self.stats
+    }
+
+    /// The syscalls available to the program, see [`crate::syscall`].
+    pub fn syscalls(&self) -> &SyscallTable {
+        &self.syscalls
+    }
+
+    /// Lets the host replace or disable syscalls.
+    pub fn syscalls_mut(&mut self) -> &mut SyscallTable {
+        &mut self.syscalls
+    }
+
+    /// The functions callable with `Callnative`, see [`crate::native`].
+    pub fn natives(&self) -> &NativeRegistry {
+        &self.natives
+    }
+
+    pub fn natives_mut(&mut self) -> &mut NativeRegistry {
+        &mut self.natives
+    }
+
+    /// Makes `function` callable by `name` or by the returned id.
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut [Operand]) -> Result<Operand, VmError> + 'static,
+    ) -> usize {
+        self.natives.register(name, arity, function)
+    }
+
+    /// Halts the vm with an exit code, as the exit syscall does.
+    pub fn exit(&mut self, code: i64) {
+        self.halted = true;
+        self.exit_code = Some(code);
+    }
+
+    /// The code passed to the exit syscall, `None` if the program did not
+    /// exit that way.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Executes instructions until `Halt` or the first error.
+    pub fn run(&mut self) -> Result<(), VmError> {
         while !self.halted {
-            self.step();
+            self.step()?;
         }
+        Ok(())
     }
 
-    fn step(&mut self) {
+    /// Executes a single instruction, doing nothing once the vm has halted.
+    pub fn step(&mut self) -> Result<(), VmError> {
         if !self.halted {
-            self.execute();
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(VmError::OutOfFuel { ip: self.ip });
+                }
+                *fuel -= 1;
+            }
+            self.execute()?;
         }
+        Ok(())
     }
 
-    fn current_frame_mut(&mut self) -> &mut Frame {
-        self.frames.front_mut().unwrap()
+    /// Adds `fuel` to the budget and runs. Returns `VmError::OutOfFuel` once
+    /// the budget is spent, after which calling this again resumes execution
+    /// from the same state.
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Result<(), VmError> {
+        self.add_fuel(fuel);
+        self.run()
     }
-    fn current_frame(&self) -> &Frame {
-        self.frames.front().unwrap()
+
+    /// Limits execution to `fuel` more instructions, `None` removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
     }
 
-    fn execute(&mut self) {
-        let value = self.nextToken();
-        match value {
-            Token::Instruction(i) => match i {
-                Instruction::Halt => self.halted = true,
-                Instruction::Pop => {
-                    assert!(self.stack.len() >= 1);
-                    self.stack.pop_front();
-                }
-                Instruction::Push => {
-                    let v = self.nextToken().try_into().unwrap();
-                    self.stack.push_front(data!(v));
-                }
-                Instruction::Dup => {
-                    assert!(self.stack.len() >= 1);
-                    let v = self.stack.pop_front().unwrap();
-                    self.stack.push_front(v.clone());
-                    self.stack.push_front(v);
-                }
-                Instruction::Jmp => {
-                    let v: Operand = self.nextToken().try_into().unwrap();
-                    self.ip = v.try_into().unwrap();
+    /// Adds to the remaining budget, starting one if there was no limit.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(self.fuel.unwrap_or(0).saturating_add(fuel));
+    }
+
+    /// Instructions left before `VmError::OutOfFuel`, `None` if unlimited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Pushes a value on top of the operand stack, e.g. to pass arguments.
+    /// Values pushed by the host are not subject to `max_stack_size`.
+    pub fn push(&mut self, value: Operand) {
+        self.stack.push_front(data!(value));
+        self.stats.max_stack_size = self.stats.max_stack_size.max(self.stack.len());
+    }
+
+    /// Pops the value on top of the operand stack, e.g. to read a result.
+    pub fn pop(&mut self) -> Option<Operand> {
+        match self.stack.pop_front()? {
+            Token::Data(v) => Some(v),
+            Token::Instruction(_) => unreachable!("only data is pushed onto the stack"),
+        }
+    }
+
+    /// The operand stack, top first.
+    pub fn stack(&self) -> &VecDeque<Token> {
+        &self.stack
+    }
+
+    pub fn program(&self) -> &[Token] {
+        &self.program
+    }
+
+    /// Appends code to the program, e.g. to feed it line by line. Names in
+    /// it get the slots they have in the rest of the program. The program is
+    /// left unchanged if the result cannot be decoded.
+    pub fn extend_program(&mut self, mut tokens: Vec<Token>) -> Result<(), VmError> {
+        let len = self.program.len();
+        let local_names = self.local_names.clone();
+        self.local_names.resolve(&mut tokens);
+        self.program.extend(tokens);
+        match op::decode(&self.program) {
+            Ok(ops) => {
+                if let Err(e) = self.resize_frames(len) {
+                    self.program.truncate(len);
+                    self.local_names = local_names;
+                    return Err(e);
                 }
-                Instruction::Jif => {
-                    assert!(self.stack.len() >= 1);
-                    let c = self.stack.pop_front().unwrap();
-                    let d1: Operand = self.nextToken().try_into().unwrap();
+                self.index = Vm::index(&ops, self.program.len());
+                self.ops = ops.into();
+                Ok(())
+            }
+            Err(e) => {
+                self.program.truncate(len);
+                self.local_names = local_names;
+                Err(e)
+            }
+        }
+    }
+
+    /// Grows every frame to the size its function now has, failing at `ip`
+    /// without changing any if they would exceed `VmConfig::max_locals`.
+    fn resize_frames(&mut self, ip: usize) -> Result<(), VmError> {
+        let sizes = self.frames.iter();
+        let locals = sizes
+            .map(|f| f.locals().len().max(self.local_names.size(f.function())))
+            .sum();
+        self.check_locals(ip, locals)?;
+        for frame in &mut self.frames {
+            frame.grow(self.local_names.size(frame.function()));
+        }
+        self.locals = locals;
+        Ok(())
+    }
+
+    fn check_locals(&mut self, ip: usize, locals: usize) -> Result<(), VmError> {
+        if let Some(limit) = self.config.max_locals {
+            if locals > limit {
+                return Err(VmError::LocalsExceeded { ip, limit });
+            }
+        }
+        self.stats.max_locals = self.stats.max_locals.max(locals);
+        Ok(())
+    }
+
+    /// Address of the next instruction to execute.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// Moves execution to `ip`, e.g. to skip code after an error.
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
 
-                    if c.try_into().unwrap() {
-                        self.ip = d1.try_into().unwrap();
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The frame of the function currently executing.
+    pub fn current_frame(&self) -> &Frame {
+        self.frames.front().unwrap()
+    }
+
+    /// All call frames, innermost first. The last one is the root frame.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter()
+    }
+
+    /// Value of the local `name` in the current frame, `Operand::Null` if it
+    /// has none.
+    pub fn variable(&self, name: &str) -> Operand {
+        let frame = self.current_frame();
+        (self.local_names.slot(frame.function(), name))
+            .and_then(|slot| frame.get(slot))
+            .map_or(Operand::Null, Operand::clone)
+    }
+
+    /// Every local of the current frame with its value, sorted by name.
+    /// Slots without a name are called `$` followed by their number.
+    pub fn variables(&self) -> Vec<(String, &Operand)> {
+        let frame = self.current_frame();
+        let names = self.local_names.names(frame.function());
+        let mut variables: Vec<_> = (frame.locals().iter().enumerate())
+            .map(|(slot, v)| match names.get(slot) {
+                Some(name) if !name.is_empty() => (name.clone(), v),
+                _ => (format!("${}", slot), v),
+            })
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        variables
+    }
+
+    /// The local `slot` of the current frame, for the instruction at `ip`.
+    fn local(&mut self, ip: usize, slot: usize) -> Result<&mut Operand, VmError> {
+        let frame = self.frames.front_mut().unwrap();
+        let size = frame.locals().len();
+        frame
+            .get_mut(slot)
+            .ok_or(VmError::SlotOutOfBounds { ip, slot, size })
+    }
+
+    fn execute(&mut self) -> Result<(), VmError> {
+        let ip = self.ip;
+        let ops = Rc::clone(&self.ops);
+        let op = match self.index.get(ip) {
+            Some(Some(n)) => &ops[*n],
+            Some(None) => return Err(VmError::DataExecuted { ip }),
+            None => return Err(VmError::IpOutOfBounds { ip }),
+        };
+        self.ip = ip + op.size();
+        let i = op.instruction();
+        match op {
+            Op::Halt => self.halted = true,
+            Op::Pop => {
+                self.pop_operand(ip, &i)?;
+            }
+            Op::Push(v) => self.push_checked(ip, v.clone())?,
+            Op::Dup => {
+                let v = self.pop_operand(ip, &i)?;
+                self.push_checked(ip, v.clone())?;
+                self.push_checked(ip, v)?;
+            }
+            Op::Jmp(target) => self.ip = *target,
+            Op::Jif(target) => {
+                let c = match self.pop_operand(ip, &i)? {
+                    Operand::Bool(c) => c,
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
                     }
+                };
+                if c {
+                    self.ip = *target;
                 }
+            }
 
-                Instruction::Not => {
-                    assert!(self.stack.len() >= 1);
-                    let v1 = self.stack.pop_front().unwrap();
-                    let r = match v1.try_into().unwrap() {
-                        true => tbool!(false),
-                        false => tbool!(true),
-                    };
-                    self.stack.push_front(r);
+            Op::Not => {
+                let r = match self.pop_operand(ip, &i)? {
+                    Operand::Bool(v) => Operand::Bool(!v),
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
+                    }
+                };
+                self.push_checked(ip, r)?;
+            }
+            // Names are given slots when the program is loaded, this only
+            // finds them again.
+            Op::Load(name) | Op::Store(name) => {
+                let function = self.current_frame().function();
+                let Some(slot) = self.local_names.slot(function, name) else {
+                    return Err(VmError::InvalidOperand {
+                        ip,
+                        instr: i,
+                        operand: Operand::Str(name.clone()),
+                    });
+                };
+                if let Op::Load(_) = op {
+                    let var = self.local(ip, slot)?.clone();
+                    self.push_checked(ip, var)?;
+                } else {
+                    let val = self.pop_operand(ip, &i)?;
+                    *self.local(ip, slot)? = val;
                 }
-                Instruction::Load => {
-                    let v1: Operand = self.nextToken().try_into().unwrap();
-                    let var = self.current_frame().get(v1.try_into().unwrap());
+            }
 
-                    self.stack.push_front(Token::Data(var));
-                }
+            Op::Loadlocal(slot) => {
+                let var = self.local(ip, *slot)?.clone();
+                self.push_checked(ip, var)?;
+            }
+            Op::Storelocal(slot) => {
+                let val = self.pop_operand(ip, &i)?;
+                *self.local(ip, *slot)? = val;
+            }
 
-                Instruction::Store => {
-                    assert!(self.stack.len() >= 1);
-                    let var: Operand = self.nextToken().try_into().unwrap();
-                    let val = self.stack.pop_front().unwrap().try_into().unwrap();
-                    self.current_frame_mut().set(var.try_into().unwrap(), val);
+            Op::Call(address) => {
+                let address = *address;
+                // The root frame is not a call, so this is the depth after calling.
+                let depth = self.frames.len();
+                if let Some(limit) = self.config.max_call_depth {
+                    if depth > limit {
+                        return Err(VmError::CallDepthExceeded { ip, limit });
+                    }
                 }
+                let size = self.local_names.size(address);
+                self.check_locals(ip, self.locals + size)?;
+                self.locals += size;
+                self.frames.push_front(Frame::new(address, self.ip, size));
+                self.stats.max_call_depth = self.stats.max_call_depth.max(depth);
 
-                Instruction::Call => {
-                    let address: Operand = self.nextToken().try_into().unwrap();
-                    assert!(address > 0 && address < self.program.len());
-                    self.frames.push_front(Frame::new(self.ip));
-
-                    self.ip = address.try_into().unwrap();
+                self.ip = address;
+            }
+            Op::Ret => {
+                if self.frames.len() <= 1 {
+                    return Err(VmError::ReturnWithoutCall { ip });
                 }
-                Instruction::Ret => {
-                    assert!(self.frames.len() > 1);
-                    let return_dddress = self.current_frame().return_address();
+                let return_dddress = self.current_frame().return_address();
 
-                    self.frames.pop_front();
+                let frame = self.frames.pop_front().unwrap();
+                self.locals -= frame.locals().len();
 
-                    self.ip = return_dddress;
+                self.ip = return_dddress;
+            }
+            Op::Write => {
+                if let Some(v) = self.stack.front() {
+                    write!(self.output, "{}", v).map_err(|e| VmError::Io {
+                        ip,
+                        message: e.to_string(),
+                    })?;
+                }
+            }
+            Op::Arrnew => {
+                let n = self.pop_int(ip, &i)?;
+                if n < 0 {
+                    return Err(VmError::InvalidOperand {
+                        ip,
+                        instr: i,
+                        operand: Operand::Int(n),
+                    });
                 }
-                Instruction::Write => {
-                    if let Some(v) = self.stack.front() {
-                        print!("{:?}", v);
+                if self.stack.len() < n as usize {
+                    return Err(VmError::StackUnderflow { ip, instr: i });
+                }
+                // The first element was pushed first, so it is the deepest.
+                let mut elements: Vec<Operand> = (0..n).map(|_| self.pop().unwrap()).collect();
+                elements.reverse();
+                self.push_checked(ip, Operand::Array(elements))?;
+            }
+            Op::Arrget => {
+                let index = self.pop_int(ip, &i)?;
+                let mut array = self.pop_array(ip, &i)?;
+                let index = Vm::array_index(ip, index, array.len())?;
+                self.push_checked(ip, array.swap_remove(index))?;
+            }
+            Op::Arrset => {
+                let value = self.pop_operand(ip, &i)?;
+                let index = self.pop_int(ip, &i)?;
+                let mut array = self.pop_array(ip, &i)?;
+                let index = Vm::array_index(ip, index, array.len())?;
+                array[index] = value;
+                self.push_checked(ip, Operand::Array(array))?;
+            }
+            Op::Arrpush => {
+                let value = self.pop_operand(ip, &i)?;
+                let mut array = self.pop_array(ip, &i)?;
+                array.push(value);
+                self.push_checked(ip, Operand::Array(array))?;
+            }
+            Op::Arrpop => {
+                let mut array = self.pop_array(ip, &i)?;
+                let value = array.pop().ok_or(VmError::IndexOutOfBounds {
+                    ip,
+                    index: -1,
+                    len: 0,
+                })?;
+                self.push_checked(ip, Operand::Array(array))?;
+                self.push_checked(ip, value)?;
+            }
+            Op::Arrlen => {
+                let array = self.pop_array(ip, &i)?;
+                self.push_checked(ip, Operand::Int(array.len() as i64))?;
+            }
+            Op::Mapnew => self.push_checked(ip, Operand::Map(HashMap::new()))?,
+            Op::Mapget => {
+                let key = self.pop_key(ip, &i)?;
+                let mut map = self.pop_map(ip, &i)?;
+                let value = map.remove(&key).unwrap_or(Operand::Null);
+                self.push_checked(ip, value)?;
+            }
+            Op::Mapset => {
+                let value = self.pop_operand(ip, &i)?;
+                let key = self.pop_key(ip, &i)?;
+                let mut map = self.pop_map(ip, &i)?;
+                map.insert(key, value);
+                self.push_checked(ip, Operand::Map(map))?;
+            }
+            Op::Mapdel => {
+                let key = self.pop_key(ip, &i)?;
+                let mut map = self.pop_map(ip, &i)?;
+                map.remove(&key);
+                self.push_checked(ip, Operand::Map(map))?;
+            }
+            Op::Maphas => {
+                let key = self.pop_key(ip, &i)?;
+                let map = self.pop_map(ip, &i)?;
+                self.push_checked(ip, Operand::Bool(map.contains_key(&key)))?;
+            }
+            Op::Mapkeys => {
+                let map = self.pop_map(ip, &i)?;
+                let keys = Operand::sorted_entries(&map)
+                    .into_iter()
+                    .map(|(k, _)| k.clone())
+                    .collect();
+                self.push_checked(ip, Operand::Array(keys))?;
+            }
+            Op::Tostr => {
+                let r = match self.pop_operand(ip, &i)? {
+                    Operand::Str(s) => s,
+                    Operand::Char(c) => c.to_string(),
+                    Operand::Bytes(b) => {
+                        String::from_utf8(b).map_err(|_| VmError::InvalidUtf8 { ip })?
+                    }
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
+                    }
+                };
+                self.push_checked(ip, Operand::Str(r))?;
+            }
+            Op::Tochar => {
+                let v = self.pop_operand(ip, &i)?;
+                let c = match &v {
+                    Operand::Char(c) => Some(*c),
+                    Operand::Str(s) => {
+                        let mut chars = s.chars();
+                        chars.next().filter(|_| chars.next().is_none())
+                    }
+                    Operand::Int(n) => u32::try_from(*n).ok().and_then(char::from_u32),
+                    _ => None,
+                };
+                let c = c.ok_or(VmError::InvalidOperand {
+                    ip,
+                    instr: i,
+                    operand: v,
+                })?;
+                self.push_checked(ip, Operand::Char(c))?;
+            }
+            Op::Tobytes => {
+                let r = match self.pop_operand(ip, &i)? {
+                    Operand::Bytes(b) => b,
+                    Operand::Str(s) => s.into_bytes(),
+                    Operand::Char(c) => c.to_string().into_bytes(),
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
+                    }
+                };
+                self.push_checked(ip, Operand::Bytes(r))?;
+            }
+            Op::Byteat => {
+                let index = self.pop_int(ip, &i)?;
+                let bytes = match self.pop_operand(ip, &i)? {
+                    Operand::Bytes(b) => b,
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
+                    }
+                };
+                let index = Vm::array_index(ip, index, bytes.len())?;
+                self.push_checked(ip, Operand::Int(bytes[index] as i64))?;
+            }
+            Op::Slice => {
+                let end = self.pop_int(ip, &i)?;
+                let start = self.pop_int(ip, &i)?;
+                let v = self.pop_operand(ip, &i)?;
+                let len = match &v {
+                    Operand::Str(s) => s.chars().count(),
+                    Operand::Bytes(b) => b.len(),
+                    Operand::Array(a) => a.len(),
+                    _ => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand: v,
+                        })
                     }
+                };
+                let range = Vm::slice_range(ip, start, end, len)?;
+                let r = match v {
+                    Operand::Str(s) => {
+                        Operand::Str(s.chars().skip(range.start).take(range.len()).collect())
+                    }
+                    Operand::Bytes(b) => Operand::Bytes(b[range].to_vec()),
+                    Operand::Array(a) => Operand::Array(a[range].to_vec()),
+                    _ => unreachable!("checked above"),
+                };
+                self.push_checked(ip, r)?;
+            }
+            Op::Syscall => {
+                let id = self.pop_int(ip, &i)?;
+                let handler = self
+                    .syscalls
+                    .get(id)
+                    .ok_or(VmError::UnknownSyscall { ip, id })?;
+                handler(self, ip)?;
+            }
+            Op::Callnative(native) => {
+                let native = native.to_operand();
+                let native = self
+                    .natives
+                    .get(&native)
+                    .cloned()
+                    .ok_or(VmError::UnknownNative { ip, native })?;
+                if self.stack.len() < native.arity {
+                    return Err(VmError::StackUnderflow { ip, instr: i });
                 }
-                Instruction::Add
-                | Instruction::Div
-                | Instruction::Mul
-                | Instruction::Sub
-                | Instruction::And
-                | Instruction::Or
-                | Instruction::Iseq
-                | Instruction::Isge
-                | Instruction::Isgt => {
-                    assert!(self.stack.len() >= 2);
-                    let d2 = self.stack.pop_front().unwrap().try_into().unwrap();
-                    let d1 = self.stack.pop_front().unwrap().try_into().unwrap();
-                    let r = Vm::execute_binary(i, d1, d2);
-                    self.stack.push_front(Token::Data(r));
+                let mut args: Vec<Operand> =
+                    (0..native.arity).map(|_| self.pop().unwrap()).collect();
+                args.reverse();
+                let r = (native.function)(&mut args).map_err(|e| match e {
+                    VmError::Native { message, .. } => VmError::Native {
+                        ip,
+                        name: native.name.clone(),
+                        message,
+                    },
+                    e => e,
+                })?;
+                self.push_checked(ip, r)?;
+            }
+            Op::Readln | Op::Read => {
+                let read = if i == Instruction::Readln {
+                    self.read_line()
+                } else {
+                    self.read_all()
+                };
+                let v = read.map_err(|e| VmError::Io {
+                    ip,
+                    message: e.to_string(),
+                })?;
+                self.push_checked(ip, v.map_or(Operand::Null, Operand::Str))?;
+            }
+            Op::Parseint | Op::Parsefloat => {
+                let s = match self.pop_operand(ip, &i)? {
+                    Operand::Str(s) => s,
+                    operand => {
+                        return Err(VmError::InvalidOperand {
+                            ip,
+                            instr: i,
+                            operand,
+                        })
+                    }
+                };
+                // Failing to parse is not an error, the program checks for null.
+                let v = if i == Instruction::Parseint {
+                    s.trim().parse().map(Operand::Int).ok()
+                } else {
+                    s.trim().parse().map(Operand::Float).ok()
+                };
+                self.push_checked(ip, v.unwrap_or(Operand::Null))?;
+            }
+            Op::Add
+            | Op::Div
+            | Op::Mul
+            | Op::Sub
+            | Op::And
+            | Op::Or
+            | Op::Iseq
+            | Op::Isge
+            | Op::Isgt => {
+                if self.stack.len() < 2 {
+                    return Err(VmError::StackUnderflow { ip, instr: i });
                 }
-            },
-            Token::Data(_) => panic!("Cannot execute on data"),
+                let d2 = self.pop_operand(ip, &i)?;
+                let d1 = self.pop_operand(ip, &i)?;
+                let r = Vm::execute_binary(ip, i, d1, d2)?;
+                self.push_checked(ip, r)?;
+            }
         }
+        Ok(())
     }
 
-    fn execute_binary(i: Instruction, d1: Operand, d2: Operand) -> Operand {
-        match i {
-            Instruction::Add => d1 + d2,
-            Instruction::Sub => d1 - d2,
-            Instruction::Mul => d1 * d2,
-            Instruction::Div => d1 / d2,
-            Instruction::And => d1 & d2,
-            Instruction::Or => d1 | d2,
-            Instruction::Isgt => (d1 > d2).into(),
-            Instruction::Isge => (d1 >= d2).into(),
-            Instruction::Iseq => (d1 == d2).into(),
+    pub(crate) fn execute_binary(
+        ip: usize,
+        i: Instruction,
+        d1: Operand,
+        d2: Operand,
+    ) -> Result<Operand, VmError> {
+        if let (Operand::Int(l), Operand::Int(r)) = (&d1, &d2) {
+            let overflows = match i {
+                Instruction::Div if *r == 0 => return Err(VmError::DivisionByZero { ip }),
+                Instruction::Add => l.checked_add(*r).is_none(),
+                Instruction::Sub => l.checked_sub(*r).is_none(),
+                Instruction::Mul => l.checked_mul(*r).is_none(),
+                Instruction::Div => l.checked_div(*r).is_none(),
+                _ => false,
+            };
+            if overflows {
+                return Err(VmError::IntegerOverflow { ip, instr: i });
+            }
+        }
+        let r = match i {
+            Instruction::Add => d1.clone() + d2.clone(),
+            Instruction::Sub => d1.clone() - d2.clone(),
+            Instruction::Mul => d1.clone() * d2.clone(),
+            Instruction::Div => d1.clone() / d2.clone(),
+            Instruction::And => d1.clone() & d2.clone(),
+            Instruction::Or => d1.clone() | d2.clone(),
+            Instruction::Isgt => Vm::compare(&d1, &d2).map(|o| o.is_gt().into()),
+            Instruction::Isge => Vm::compare(&d1, &d2).map(|o| o.is_ge().into()),
+            Instruction::Iseq => Some(match (&d1, &d2) {
+                // Unlike `Operand`'s `Eq`, NaN is not equal to itself here.
+                (Operand::Float(l), Operand::Float(r)) => (l == r).into(),
+                _ => (d1 == d2).into(),
+            }),
             Instruction::Dup
             | Instruction::Halt
             | Instruction::Pop
@@ -161,31 +848,172 @@ impl Vm {
             | Instruction::Store
             | Instruction::Call
             | Instruction::Write
-            | Instruction::Ret => panic!("Not a binary op"),
+            | Instruction::Arrnew
+            | Instruction::Arrget
+            | Instruction::Arrset
+            | Instruction::Arrpush
+            | Instruction::Arrpop
+            | Instruction::Arrlen
+            | Instruction::Mapnew
+            | Instruction::Mapget
+            | Instruction::Mapset
+            | Instruction::Mapdel
+            | Instruction::Maphas
+            | Instruction::Mapkeys
+            | Instruction::Tostr
+            | Instruction::Tochar
+            | Instruction::Tobytes
+            | Instruction::Byteat
+            | Instruction::Slice
+            | Instruction::Syscall
+            | Instruction::Callnative
+            | Instruction::Readln
+            | Instruction::Read
+            | Instruction::Parseint
+            | Instruction::Parsefloat
+            | Instruction::Loadlocal
+            | Instruction::Storelocal
+            | Instruction::Ret => unreachable!("Not a binary op"),
+        };
+        r.ok_or(VmError::TypeMismatch {
+            ip,
+            instr: i,
+            lhs: d1,
+            rhs: d2,
+        })
+    }
+
+    /// Orders two operands of the same type. Floats that cannot be ordered
+    /// (NaN) compare as less so the comparison simply yields `false`, and
+    /// booleans, arrays and maps have no order even when equal.
+    fn compare(d1: &Operand, d2: &Operand) -> Option<std::cmp::Ordering> {
+        match (d1, d2) {
+            (Operand::Float(l), Operand::Float(r)) => {
+                Some(l.partial_cmp(r).unwrap_or(std::cmp::Ordering::Less))
+            }
+            (Operand::Int(_), Operand::Int(_))
+            | (Operand::Str(_), Operand::Str(_))
+            | (Operand::Char(_), Operand::Char(_))
+            | (Operand::Bytes(_), Operand::Bytes(_)) => d1.partial_cmp(d2),
+            _ => None,
         }
     }
 
-    fn nextToken(&mut self) -> Token {
-        if self.ip >= self.program.len() {
-            panic!("IP out of bounds")
+    /// Pushes a value produced by the instruction at `ip`, enforcing the
+    /// stack limit.
+    pub(crate) fn push_checked(&mut self, ip: usize, v: Operand) -> Result<(), VmError> {
+        if let Some(limit) = self.config.max_stack_size {
+            if self.stack.len() >= limit {
+                return Err(VmError::StackOverflow { ip, limit });
+            }
         }
-        let v = &self.program[self.ip];
-        self.ip += 1;
-        v.to_owned()
+        self.push(v);
+        Ok(())
+    }
+
+    fn pop_operand(&mut self, ip: usize, i: &Instruction) -> Result<Operand, VmError> {
+        self.pop().ok_or(VmError::StackUnderflow {
+            ip,
+            instr: i.clone(),
+        })
+    }
+
+    fn pop_int(&mut self, ip: usize, i: &Instruction) -> Result<i64, VmError> {
+        match self.pop_operand(ip, i)? {
+            Operand::Int(v) => Ok(v),
+            operand => Err(VmError::InvalidOperand {
+                ip,
+                instr: i.clone(),
+                operand,
+            }),
+        }
+    }
+
+    fn pop_array(&mut self, ip: usize, i: &Instruction) -> Result<Vec<Operand>, VmError> {
+        match self.pop_operand(ip, i)? {
+            Operand::Array(v) => Ok(v),
+            operand => Err(VmError::InvalidOperand {
+                ip,
+                instr: i.clone(),
+                operand,
+            }),
+        }
+    }
+
+    fn pop_map(
+        &mut self,
+        ip: usize,
+        i: &Instruction,
+    ) -> Result<HashMap<Operand, Operand>, VmError> {
+        match self.pop_operand(ip, i)? {
+            Operand::Map(v) => Ok(v),
+            operand => Err(VmError::InvalidOperand {
+                ip,
+                instr: i.clone(),
+                operand,
+            }),
+        }
+    }
+
+    /// Pops a value that can be used as a map key.
+    fn pop_key(&mut self, ip: usize, i: &Instruction) -> Result<Operand, VmError> {
+        match self.pop_operand(ip, i)? {
+            key if key.is_hashable() => Ok(key),
+            operand => Err(VmError::InvalidOperand {
+                ip,
+                instr: i.clone(),
+                operand,
+            }),
+        }
+    }
+
+    /// Checks that `index` is within an array of length `len`.
+    fn array_index(ip: usize, index: i64, len: usize) -> Result<usize, VmError> {
+        usize::try_from(index)
+            .ok()
+            .filter(|index| *index < len)
+            .ok_or(VmError::IndexOutOfBounds { ip, index, len })
+    }
+
+    /// Checks `start..end` against a sequence of length `len`. Strings are
+    /// sliced by character, not by byte.
+    fn slice_range(ip: usize, start: i64, end: i64, len: usize) -> Result<Range<usize>, VmError> {
+        let bound = |index: i64, min: usize| {
+            usize::try_from(index)
+                .ok()
+                .filter(|index| (min..=len).contains(index))
+                .ok_or(VmError::IndexOutOfBounds { ip, index, len })
+        };
+        let start = bound(start, 0)?;
+        let end = bound(end, start)?;
+        Ok(start..end)
     }
 }
 
+#[cfg(test)]
 mod test {
     use std::collections::VecDeque;
 
-    use crate::{data, int, stack, tbool, tint, token::instruction::*, tstr};
+    use crate::{
+        asm, data,
+        error::VmError,
+        frame::LocalNames,
+        output::Capture,
+        stack, tbool, tint,
+        token::{
+            instruction::{Instruction, *},
+            operand::Operand,
+            Token,
+        },
+        tstr,
+    };
 
-    use super::Vm;
+    use super::{Vm, VmConfig};
 
     #[test]
     fn push_halt() {
-        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), HALT]).unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 5);
         assert!(vm.halted);
 
@@ -193,8 +1021,8 @@ mod test {
     }
     #[test]
     fn add() {
-        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), ADD, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), ADD, HALT]).unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 6);
         assert!(vm.halted);
         assert_eq!(vm.stack, stack![tint!(22)]);
@@ -202,33 +1030,38 @@ mod test {
 
     #[test]
     fn sub() {
-        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), SUB, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), SUB, HALT]).unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 6);
         assert!(vm.halted);
         assert_eq!(vm.stack, stack![tint!(-2)]);
     }
     #[test]
     fn mul() {
-        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), MUL, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(10), PUSH, tint!(12), MUL, HALT]).unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 6);
         assert!(vm.halted);
         assert_eq!(vm.stack, stack![tint!(120)]);
     }
     #[test]
     fn divide() {
-        let mut vm = Vm::new(vec![PUSH, tint!(20), PUSH, tint!(2), DIV, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(20), PUSH, tint!(2), DIV, HALT]).unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 6);
         assert!(vm.halted);
         assert_eq!(vm.stack, stack![tint!(10)]);
     }
     #[test]
-    #[should_panic]
     fn test_no_sufficient_params() {
-        let mut vm = Vm::new(vec![SUB, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![SUB, HALT]).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackUnderflow {
+                ip: 0,
+                instr: Instruction::Sub
+            })
+        );
     }
     #[test]
     fn evaluate_expressions() {
@@ -248,8 +1081,9 @@ mod test {
             tint!(7),
             DIV,
             HALT,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert_eq!(vm.ip, 12);
         assert!(vm.halted);
         assert_eq!(vm.stack, stack![tint!(1)]);
@@ -257,30 +1091,35 @@ mod test {
 
     #[test]
     fn test_not() {
-        let mut vm = Vm::new(vec![PUSH, tbool!(true), NOT, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tbool!(true), NOT, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 4);
         assert_eq!(vm.stack, stack![tbool!(false)]);
 
-        let mut vm = Vm::new(vec![PUSH, tbool!(false), NOT, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tbool!(false), NOT, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 4);
         assert_eq!(vm.stack, stack![tbool!(true)]);
     }
 
     #[test]
-    #[should_panic]
     fn uniary_inseffiient() {
-        let mut vm = Vm::new(vec![NOT, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![NOT, HALT]).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackUnderflow {
+                ip: 0,
+                instr: Instruction::Not
+            })
+        );
     }
 
     #[test]
     fn test_and_true() {
-        let mut vm = Vm::new(vec![PUSH, tbool!(true), PUSH, tbool!(true), AND, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tbool!(true), PUSH, tbool!(true), AND, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
@@ -288,8 +1127,8 @@ mod test {
 
     #[test]
     fn test_or() {
-        let mut vm = Vm::new(vec![PUSH, tbool!(true), PUSH, tbool!(false), OR, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tbool!(true), PUSH, tbool!(false), OR, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
@@ -297,23 +1136,28 @@ mod test {
 
     #[test]
     fn test_pop() {
-        let mut vm = Vm::new(vec![PUSH, tint!(1), POP, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(1), POP, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 4);
         assert!(vm.stack.is_empty())
     }
     #[test]
-    #[should_panic]
     fn test_pop_insufficient() {
-        let mut vm = Vm::new(vec![POP, HALT]);
-        vm.step();
+        let mut vm = Vm::new(vec![POP, HALT]).unwrap();
+        assert_eq!(
+            vm.step(),
+            Err(VmError::StackUnderflow {
+                ip: 0,
+                instr: Instruction::Pop
+            })
+        );
     }
 
     #[test]
     fn test_dup() {
-        let mut vm = Vm::new(vec![PUSH, tint!(1), DUP, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(1), DUP, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 4);
         assert_eq!(vm.stack, stack![tint!(1), tint!(1)]);
@@ -321,14 +1165,14 @@ mod test {
 
     #[test]
     fn test_is_greater() {
-        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(2), ISGT, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(2), ISGT, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(false)]);
 
-        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISGT, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISGT, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
@@ -336,14 +1180,14 @@ mod test {
 
     #[test]
     fn test_is_greater_eq() {
-        let mut vm = Vm::new(vec![PUSH, tint!(3), PUSH, tint!(2), ISGE, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(3), PUSH, tint!(2), ISGE, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
 
-        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISGE, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISGE, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
@@ -351,14 +1195,14 @@ mod test {
 
     #[test]
     fn test_is_eq() {
-        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(1), ISEQ, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(1), ISEQ, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(true)]);
 
-        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISEQ, HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(2), PUSH, tint!(1), ISEQ, HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 6);
         assert_eq!(vm.stack, stack![tbool!(false)]);
@@ -366,8 +1210,8 @@ mod test {
 
     #[test]
     fn test_jump() {
-        let mut vm = Vm::new(vec![JMP, tint!(3), HALT, JMP, tint!(2)]);
-        vm.run();
+        let mut vm = Vm::new(vec![JMP, tint!(3), HALT, JMP, tint!(2)]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 3);
     }
@@ -385,27 +1229,28 @@ mod test {
             JIF,
             tint!(4),
             HALT,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 10);
     }
 
     #[test]
     fn test_load() {
-        let mut vm = Vm::new(vec![LOAD, tstr!(String::from("a")), HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![LOAD, tstr!(String::from("a")), HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 3);
     }
     #[test]
     fn test_store() {
-        let mut vm = Vm::new(vec![PUSH, tint!(42), STORE, tstr!(String::from("a")), HALT]);
-        vm.run();
+        let mut vm = Vm::new(vec![PUSH, tint!(42), STORE, tstr!(String::from("a")), HALT]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 5);
         assert!(vm.stack.is_empty());
-        assert_eq!(vm.current_frame().values(), vec![42]);
+        assert_eq!(vm.current_frame().locals(), vec![42]);
     }
 
     #[test]
@@ -418,33 +1263,239 @@ mod test {
             LOAD,
             tstr!(String::from("a")),
             HALT,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 7);
-        assert_eq!(vm.current_frame().values(), vec![42]);
+        assert_eq!(vm.current_frame().locals(), vec![42]);
         assert_eq!(vm.stack, stack![tint!(42)]);
     }
 
     #[test]
-    #[should_panic]
+    fn test_locals() {
+        let program = asm::assemble(
+            r#"
+                push 1
+                storelocal 1
+                push 2
+                call f
+                halt
+            f:  storelocal 0
+                loadlocal 0
+                storelocal 1
+                ret"#,
+        )
+        .unwrap();
+        let mut names = LocalNames::new();
+        names.insert(9, vec![String::from("x"), String::from("y")]);
+        let mut vm = Vm::new(program).unwrap().with_local_names(names);
+        for _ in 0..7 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.variable("x"), 2);
+        assert_eq!(vm.variable("y"), 2);
+        assert_eq!(vm.variable("z"), Operand::Null);
+        assert_eq!(
+            vm.variables(),
+            [
+                (String::from("x"), &Operand::Int(2)),
+                (String::from("y"), &Operand::Int(2))
+            ]
+        );
+        vm.run().unwrap();
+        // The root frame has a slot for every slot the program uses.
+        assert_eq!(
+            vm.variables(),
+            [
+                (String::from("$0"), &Operand::Null),
+                (String::from("$1"), &Operand::Int(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_local_names() {
+        let program = asm::assemble(
+            r#"push 1
+                store "a"
+                call f
+                halt
+            f:  push 2
+                storelocal 0
+                ret"#,
+        )
+        .unwrap();
+        let mut names = LocalNames::new();
+        names.insert(7, vec![String::from("x")]);
+        let mut vm = Vm::new(program).unwrap().with_local_names(names);
+        // `a` comes after the slot the program uses directly.
+        assert_eq!(vm.local_names().slot(0, "a"), Some(1));
+        assert_eq!(vm.local_names().slot(7, "x"), Some(0));
+        assert_eq!(vm.local_names().size(7), 2);
+        vm.run().unwrap();
+        assert_eq!(vm.variable("a"), 1);
+    }
+
+    #[test]
+    fn test_slot_out_of_bounds() {
+        let program = asm::assemble("call f\nhalt\nf: push 1\nstorelocal 2\nret").unwrap();
+        let mut names = LocalNames::new();
+        names.insert(3, vec![String::from("x")]);
+        let mut vm = Vm::new(program).unwrap().with_local_names(names);
+        assert_eq!(
+            vm.run(),
+            Err(VmError::SlotOutOfBounds {
+                ip: 5,
+                slot: 2,
+                size: 1
+            })
+        );
+
+        let program = asm::assemble("push 1\nstorelocal 4000000000000000000\nhalt").unwrap();
+        assert_eq!(
+            Vm::new(program).err(),
+            Some(VmError::InvalidOperand {
+                ip: 2,
+                instr: Instruction::Storelocal,
+                operand: Operand::Int(4000000000000000000)
+            })
+        );
+    }
+
+    #[test]
     fn test_load_panic() {
-        let mut vm = Vm::new(vec![LOAD]);
-        vm.run();
+        assert_eq!(
+            Vm::new(vec![LOAD]).err(),
+            Some(VmError::MissingOperand {
+                ip: 0,
+                instr: Instruction::Load
+            })
+        );
     }
 
     #[test]
-    #[should_panic]
     fn test_store_panic() {
-        let mut vm = Vm::new(vec![STORE]);
-        vm.run();
+        assert_eq!(
+            Vm::new(vec![STORE]).err(),
+            Some(VmError::MissingOperand {
+                ip: 0,
+                instr: Instruction::Store
+            })
+        );
     }
 
     #[test]
-    #[should_panic]
     fn test_store_panic2() {
-        let mut vm = Vm::new(vec![STORE, tint!(0), HALT]);
-        vm.run();
+        assert_eq!(
+            Vm::new(vec![STORE, tint!(0), HALT]).err(),
+            Some(VmError::InvalidOperand {
+                ip: 0,
+                instr: Instruction::Store,
+                operand: Operand::Int(0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let mut vm = Vm::new(vec![
+            PUSH,
+            tint!(1),
+            PUSH,
+            tstr!(String::from("a")),
+            ADD,
+            HALT,
+        ])
+        .unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::TypeMismatch {
+                ip: 4,
+                instr: Instruction::Add,
+                lhs: Operand::Int(1),
+                rhs: Operand::Str(String::from("a"))
+            })
+        );
+
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tbool!(true), ISGT, HALT]).unwrap();
+        assert!(matches!(vm.run(), Err(VmError::TypeMismatch { ip: 4, .. })));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(0), DIV, HALT]).unwrap();
+        assert_eq!(vm.run(), Err(VmError::DivisionByZero { ip: 4 }));
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let overflow = |a: i64, b: i64, instr: Token| {
+            let mut vm = Vm::new(vec![
+                PUSH,
+                data!(Operand::Int(a)),
+                PUSH,
+                data!(Operand::Int(b)),
+                instr,
+                HALT,
+            ])
+            .unwrap();
+            vm.run()
+        };
+        let error = |instr| Err(VmError::IntegerOverflow { ip: 4, instr });
+        assert_eq!(overflow(i64::MAX, 1, ADD), error(Instruction::Add));
+        assert_eq!(overflow(i64::MIN, 1, SUB), error(Instruction::Sub));
+        assert_eq!(overflow(i64::MAX, 2, MUL), error(Instruction::Mul));
+        assert_eq!(overflow(i64::MIN, -1, DIV), error(Instruction::Div));
+        assert_eq!(overflow(i64::MAX, -1, ADD), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_jump_target() {
+        assert_eq!(
+            Vm::new(vec![JMP, tint!(10), HALT]).err(),
+            Some(VmError::InvalidJumpTarget {
+                ip: 0,
+                target: Operand::Int(10)
+            })
+        );
+        assert!(matches!(
+            Vm::new(vec![CALL, tint!(-1), HALT]),
+            Err(VmError::InvalidJumpTarget { ip: 0, .. })
+        ));
+        // Targets are checked when loading, even if the branch is never taken.
+        assert!(matches!(
+            Vm::new(vec![PUSH, tbool!(false), JIF, tint!(10), HALT]),
+            Err(VmError::InvalidJumpTarget { ip: 2, .. })
+        ));
+        // Jumps must land on an instruction, not on an operand.
+        assert!(matches!(
+            Vm::new(vec![JMP, tint!(1), HALT]),
+            Err(VmError::InvalidJumpTarget { ip: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_data_executed() {
+        assert_eq!(
+            Vm::new(vec![HALT, tint!(1)]).err(),
+            Some(VmError::DataExecuted { ip: 1 })
+        );
+        let mut vm = Vm::new(vec![PUSH, tint!(1), HALT]).unwrap();
+        vm.set_ip(1);
+        assert_eq!(vm.run(), Err(VmError::DataExecuted { ip: 1 }));
+    }
+
+    #[test]
+    fn test_ip_out_of_bounds() {
+        let mut vm = Vm::new(vec![PUSH, tint!(1)]).unwrap();
+        assert_eq!(vm.run(), Err(VmError::IpOutOfBounds { ip: 2 }));
+    }
+
+    #[test]
+    fn test_return_without_call() {
+        let mut vm = Vm::new(vec![RET]).unwrap();
+        assert_eq!(vm.run(), Err(VmError::ReturnWithoutCall { ip: 0 }));
     }
     #[test]
     fn test_if() {
@@ -494,19 +1545,20 @@ mod test {
             tstr!(c.clone()),
             // Done, this is address 25
             HALT,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert!(vm.stack.is_empty());
-        assert_eq!(vm.current_frame().get(a), 6);
-        assert_eq!(vm.current_frame().get(b), 4);
-        assert_eq!(vm.current_frame().get(c), 6);
+        assert_eq!(vm.variable(&a), 6);
+        assert_eq!(vm.variable(&b), 4);
+        assert_eq!(vm.variable(&c), 6);
     }
 
     #[test]
     fn test_func_no_arguments_no_return() {
-        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, RET]);
-        vm.run();
+        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, RET]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 3);
         assert!(vm.stack.is_empty());
@@ -514,8 +1566,8 @@ mod test {
 
     #[test]
     fn test_func_no_arguments_with_return() {
-        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, PUSH, tint!(7), RET]);
-        vm.run();
+        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, PUSH, tint!(7), RET]).unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 3);
         assert_eq!(vm.stack, stack![tint!(7)]);
@@ -533,8 +1585,9 @@ mod test {
             tint!(2),
             MUL,
             RET,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 5);
         assert_eq!(vm.stack, stack![tint!(6)]);
@@ -578,19 +1631,488 @@ mod test {
             LOAD, //21
             tstr!(a.clone()),
             RET,
-        ]);
-        vm.run();
+        ])
+        .unwrap();
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 7);
         assert_eq!(vm.stack, stack![tint!(6)]);
     }
 
     #[test]
-    // Run the test with --nocapture to see the output.
+    fn test_push_arguments_pop_result() {
+        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, MUL, RET]).unwrap();
+        vm.push(Operand::Int(6));
+        vm.push(Operand::Int(7));
+        vm.step().unwrap();
+        assert_eq!(vm.ip(), 3);
+        assert_eq!(vm.frames().count(), 2);
+        assert_eq!(vm.current_frame().return_address(), 2);
+
+        vm.run().unwrap();
+        assert!(vm.is_halted());
+        assert_eq!(vm.frames().count(), 1);
+        assert_eq!(vm.pop(), Some(Operand::Int(42)));
+        assert_eq!(vm.pop(), None);
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut vm = Vm::new(vec![JMP, tint!(0)]).unwrap();
+        assert_eq!(vm.run_with_fuel(10), Err(VmError::OutOfFuel { ip: 0 }));
+        assert_eq!(vm.fuel(), Some(0));
+
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(2), ADD, HALT]).unwrap();
+        vm.set_fuel(Some(2));
+        assert_eq!(vm.run(), Err(VmError::OutOfFuel { ip: 4 }));
+        assert_eq!(vm.stack, stack![tint!(2), tint!(1)]);
+        // Running out of fuel keeps the vm resumable.
+        assert_eq!(vm.run(), Err(VmError::OutOfFuel { ip: 4 }));
+        assert_eq!(vm.run_with_fuel(1), Err(VmError::OutOfFuel { ip: 5 }));
+        assert_eq!(vm.stack, stack![tint!(3)]);
+        assert_eq!(vm.run_with_fuel(5), Ok(()));
+        assert!(vm.halted);
+        assert_eq!(vm.fuel(), Some(4));
+
+        vm.set_fuel(None);
+        assert_eq!(vm.fuel(), None);
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let config = VmConfig {
+            max_stack_size: Some(2),
+            ..VmConfig::default()
+        };
+        let mut vm = Vm::with_config(vec![PUSH, tint!(1), DUP, DUP, HALT], config.clone()).unwrap();
+        assert_eq!(vm.run(), Err(VmError::StackOverflow { ip: 3, limit: 2 }));
+        assert_eq!(vm.stats().max_stack_size, 2);
+
+        let mut vm = Vm::with_config(vec![PUSH, tint!(1), DUP, ADD, HALT], config).unwrap();
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.stats().max_stack_size, 2);
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        // Recurses forever.
+        let program = vec![CALL, tint!(0)];
+        let config = VmConfig {
+            max_call_depth: Some(100),
+            ..VmConfig::default()
+        };
+        let mut vm = Vm::with_config(program, config).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::CallDepthExceeded { ip: 0, limit: 100 })
+        );
+        assert_eq!(vm.frames().count(), 101);
+        assert_eq!(vm.stats().max_call_depth, 100);
+
+        let mut vm = Vm::new(vec![CALL, tint!(3), HALT, CALL, tint!(6), RET, RET]).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.stats().max_call_depth, 2);
+    }
+
+    #[test]
+    fn test_locals_limit() {
+        // Recurses forever with a large frame.
+        let program = asm::assemble("call f\nhalt\nf: push 1\nstorelocal 65535\ncall f").unwrap();
+        let config = VmConfig {
+            max_locals: Some(200_000),
+            ..VmConfig::default()
+        };
+        let mut vm = Vm::with_config(program.clone(), config).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::LocalsExceeded {
+                ip: 7,
+                limit: 200_000
+            })
+        );
+        assert_eq!(vm.frames().count(), 3);
+        assert_eq!(vm.stats().max_locals, 3 << 16);
+
+        let config = VmConfig {
+            max_locals: Some(10),
+            ..VmConfig::default()
+        };
+        assert_eq!(
+            Vm::with_config(program, config).err(),
+            Some(VmError::LocalsExceeded { ip: 0, limit: 10 })
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut vm = Vm::new(vec![
+            PUSH,
+            tint!(3),
+            PUSH,
+            tint!(1),
+            PUSH,
+            tint!(2),
+            PUSH,
+            tint!(3),
+            ARRNEW,
+            DUP,
+            PUSH,
+            tint!(0),
+            ARRGET,
+            STORE,
+            tstr!(String::from("first")),
+            PUSH,
+            tint!(1),
+            PUSH,
+            tstr!(String::from("b")),
+            ARRSET,
+            PUSH,
+            tint!(4),
+            ARRPUSH,
+            ARRPOP,
+            POP,
+            DUP,
+            ARRLEN,
+            HALT,
+        ])
+        .unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.variable("first"), 3);
+        assert_eq!(vm.pop(), Some(Operand::Int(3)));
+        assert_eq!(
+            vm.pop(),
+            Some(Operand::Array(vec![
+                Operand::Int(3),
+                Operand::Str(String::from("b")),
+                Operand::Int(2),
+            ]))
+        );
+
+        let mut vm = Vm::new(vec![PUSH, tint!(0), ARRNEW, HALT]).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Array(vec![])));
+    }
+
+    #[test]
+    fn test_array_errors() {
+        let empty = || vec![PUSH, tint!(0), ARRNEW];
+        let mut vm = Vm::new([empty(), vec![PUSH, tint!(0), ARRGET]].concat()).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::IndexOutOfBounds {
+                ip: 5,
+                index: 0,
+                len: 0
+            })
+        );
+
+        let mut vm = Vm::new([empty(), vec![ARRPOP]].concat()).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::IndexOutOfBounds {
+                ip: 3,
+                index: -1,
+                len: 0
+            })
+        );
+
+        let mut vm =
+            Vm::new([empty(), vec![PUSH, tint!(-1), PUSH, tint!(7), ARRSET]].concat()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::IndexOutOfBounds { index: -1, .. })
+        ));
+
+        let mut vm = Vm::new(vec![PUSH, tint!(1), ARRLEN]).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::InvalidOperand {
+                ip: 2,
+                instr: Instruction::Arrlen,
+                operand: Operand::Int(1)
+            })
+        );
+
+        let mut vm = Vm::new(vec![PUSH, tint!(1), PUSH, tint!(2), ARRNEW]).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackUnderflow {
+                ip: 4,
+                instr: Instruction::Arrnew
+            })
+        );
+
+        let mut vm = Vm::new(vec![PUSH, tint!(-1), ARRNEW]).unwrap();
+        assert!(matches!(vm.run(), Err(VmError::InvalidOperand { .. })));
+    }
+
+    #[test]
+    fn test_maps() {
+        let program = asm::assemble(
+            r#"mapnew
+            push "host"
+            push "example.com"
+            mapset
+            push 80
+            push true
+            mapset
+            push "host"
+            push "localhost"
+            mapset
+            store "m"
+            load "m"
+            push "host"
+            mapget
+            load "m"
+            push "port"
+            mapget
+            load "m"
+            push 80
+            maphas
+            load "m"
+            push 80
+            mapdel
+            dup
+            push 80
+            maphas
+            load "m"
+            mapkeys
+            halt"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        assert_eq!(
+            vm.pop(),
+            Some(Operand::Array(vec![
+                Operand::Int(80),
+                Operand::Str(String::from("host"))
+            ]))
+        );
+        assert_eq!(vm.pop(), Some(Operand::Bool(false)));
+        assert!(matches!(vm.pop(), Some(Operand::Map(m)) if m.len() == 1));
+        assert_eq!(vm.pop(), Some(Operand::Bool(true)));
+        assert_eq!(vm.pop(), Some(Operand::Null));
+        assert_eq!(vm.pop(), Some(Operand::Str(String::from("localhost"))));
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let mut vm = Vm::new(
+            asm::assemble(
+                "mapnew
+push 1.5
+push 1
+mapset",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::InvalidOperand {
+                ip: 5,
+                instr: Instruction::Mapset,
+                operand: Operand::Float(1.5)
+            })
+        );
+
+        let mut vm = Vm::new(
+            asm::assemble(
+                "push [1]
+push 0
+mapget",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::InvalidOperand {
+                operand: Operand::Array(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_float_equality() {
+        let mut vm = Vm::new(
+            asm::assemble(
+                "push NaN
+dup
+iseq
+halt",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Bool(false)));
+
+        let mut vm = Vm::new(asm::assemble("push NaN\ndup\nisge\nhalt").unwrap()).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Bool(false)));
+
+        // Equal values of a type without an order still cannot be compared.
+        let mut vm = Vm::new(asm::assemble("push true\ndup\nisge\nhalt").unwrap()).unwrap();
+        assert!(matches!(vm.run(), Err(VmError::TypeMismatch { ip: 3, .. })));
+    }
+
+    #[test]
+    fn test_chars_and_bytes() {
+        let program = asm::assemble(
+            r#"push "hé"
+            tobytes
+            dup
+            push 2
+            byteat
+            store "b"
+            tostr
+            push '!'
+            add
+            dup
+            push 1
+            push 3
+            slice
+            store "s"
+            push 104
+            tochar
+            store "c"
+            push [1, 2, 3]
+            push 1
+            push 2
+            slice
+            halt"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new(program).unwrap();
+        vm.run().unwrap();
+        let var = |name: &str| vm.variable(name);
+        assert_eq!(var("b"), Operand::Int(0xa9));
+        assert_eq!(var("s"), Operand::Str(String::from("é!")));
+        assert_eq!(var("c"), Operand::Char('h'));
+        assert_eq!(vm.pop(), Some(Operand::Array(vec![Operand::Int(2)])));
+        assert_eq!(vm.pop(), Some(Operand::Str(String::from("hé!"))));
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let mut vm = Vm::new(asm::assemble("push b\"\\xff\"\ntostr").unwrap()).unwrap();
+        assert_eq!(vm.run(), Err(VmError::InvalidUtf8 { ip: 2 }));
+
+        let mut vm = Vm::new(asm::assemble("push \"ab\"\ntochar").unwrap()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::InvalidOperand { ip: 2, .. })
+        ));
+
+        let mut vm =
+            Vm::new(asm::assemble("push \"abc\"\npush 2\npush 4\nslice").unwrap()).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::IndexOutOfBounds {
+                ip: 6,
+                index: 4,
+                len: 3
+            })
+        );
+
+        let mut vm =
+            Vm::new(asm::assemble("push b\"ab\"\npush 2\npush 1\nslice").unwrap()).unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::IndexOutOfBounds { index: 1, .. })
+        ));
+    }
+
+    #[test]
     fn test_write_stdout() {
-        let mut vm = Vm::new(vec![PUSH, tint!(3), WRITE, HALT]);
-        vm.run();
+        let capture = Capture::new();
+        let mut vm = Vm::new(vec![PUSH, tint!(3), WRITE, HALT])
+            .unwrap()
+            .with_output(capture.clone());
+        vm.run().unwrap();
         assert!(vm.halted);
         assert_eq!(vm.ip, 4);
+        assert_eq!(capture.contents(), "3");
+    }
+
+    #[test]
+    fn test_input() {
+        let program = asm::assemble(
+            r#"loop:
+                readln
+                dup
+                push null
+                iseq
+                jif done
+                parseint
+                dup
+                push null
+                iseq
+                not
+                jif loop
+                pop
+                jmp loop
+            done:
+                pop
+                read
+                halt"#,
+        )
+        .unwrap();
+        let input = std::io::Cursor::new("1\n x\n 22 \r\n\n3");
+        let mut vm = Vm::new(program).unwrap().with_input(input);
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Null));
+        assert_eq!(vm.pop(), Some(Operand::Int(3)));
+        assert_eq!(vm.pop(), Some(Operand::Int(22)));
+        assert_eq!(vm.pop(), Some(Operand::Int(1)));
+        assert_eq!(vm.pop(), None);
+
+        let mut vm = Vm::new(asm::assemble("readln\nread\nhalt").unwrap())
+            .unwrap()
+            .with_input(std::io::Cursor::new("a\nb\nc"));
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Str(String::from("b\nc"))));
+        assert_eq!(vm.pop(), Some(Operand::Str(String::from("a"))));
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut vm = Vm::new(
+            asm::assemble("push \"2.5\"\nparsefloat\npush \"2.5\"\nparseint\nhalt").unwrap(),
+        )
+        .unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.pop(), Some(Operand::Null));
+        assert_eq!(vm.pop(), Some(Operand::Float(2.5)));
+
+        let mut vm = Vm::new(asm::assemble("push 1\nparseint").unwrap()).unwrap();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::InvalidOperand {
+                ip: 2,
+                instr: Instruction::Parseint,
+                operand: Operand::Int(1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_error() {
+        struct Closed;
+        impl std::io::Write for Closed {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut vm = Vm::new(vec![PUSH, tint!(3), WRITE, HALT])
+            .unwrap()
+            .with_output(Closed);
+        assert!(matches!(vm.run(), Err(VmError::Io { ip: 2, .. })));
     }
 }